@@ -0,0 +1,49 @@
+#![no_main] // Prevents the compiler from "emitting the main symbol for an executable binary".
+#![no_std] // Prevents the linking of Rust's standard library.
+
+//! A freestanding kernel based on example code in the `bootloader` and `bootloader_api` crates, and
+//! Philipp Oppermann's blog on writing a kernel in Rust at <https://os.phil-opp.com/>.
+//!
+//! The kernel passes a configuration to the bootloader requesting a fixed mapping of physical
+//! memory, a larger kernel stack and a minimum framebuffer resolution. At boot, it sends the
+//! requested and provided values to QEMU's debugging console via `print` and `println` macros,
+//! checks that the bootloader honoured the configuration, then loops forever.
+
+use core::panic::PanicInfo;
+
+mod boot_config;
+mod qemu_console;
+
+// Specifies the name of the function that should be invoked by the bootloader when it hands
+// control to this code, and the configuration the bootloader should use when loading the kernel.
+// The function name is arbitrary.
+bootloader_api::entry_point!(simpleos_main, config = &boot_config::BOOTLOADER_CONFIG);
+
+/// The bootloader invokes this function at the end of its boot process when it is ready to hand
+/// control to the kernel. This implementation reports the bootloader configuration, then loops
+/// forever.
+fn simpleos_main(bootinfo: &'static mut bootloader_api::BootInfo) -> ! {
+    boot_config::log_and_validate(bootinfo);
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
+
+/// Rust requires a function with the "panic_handler" attribute [1] to be defined. This is usually
+/// called if a panic occurs, except that this is overridden by the `panic = "abort"` lines in
+/// Cargo.toml in this project to keep things simple. The function name is arbirary as only the
+/// attribute is used to identify which function should be called.
+///
+/// This function prints a message indicating that the kernel has panicked and the debug output
+/// of the `PanicInfo` object passed, which includes the panic message and the line of code where
+/// the panic occurred.
+///
+/// [1]: https://doc.rust-lang.org/reference/runtime.html#the-panic_handler-attribute
+#[panic_handler]
+fn panic(panic_info: &PanicInfo) -> ! {
+    println!("\nKERNEL PANIC");
+    println!("{panic_info:#?}");
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
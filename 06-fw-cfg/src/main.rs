@@ -0,0 +1,97 @@
+#![no_main] // Prevents the compiler from "emitting the main symbol for an executable binary".
+#![no_std] // Prevents the linking of Rust's standard library.
+
+//! A freestanding kernel based on example code in the `bootloader` and `bootloader_api` crates, and
+//! Philipp Oppermann's blog on writing a kernel in Rust at <https://os.phil-opp.com/>.
+//!
+//! At boot, it checks the configuration provided by the bootloader, then uses QEMU's fw_cfg
+//! interface to list the files QEMU makes available to the guest. The contents of any files passed
+//! by the host with `add_uefi_boot`'s `--fw-cfg` option are sent to QEMU's debugging console, then
+//! the kernel loops forever.
+
+use core::panic::PanicInfo;
+use fw_cfg::{FwCfgFile, FW_CFG, HOST_FILE_PREFIX};
+
+mod boot_config;
+mod fw_cfg;
+mod memory;
+mod qemu_console;
+
+// The maximum number of files passed by the host that are output, and the number of bytes output
+// from each.
+const MAX_HOST_FILES: usize = 8;
+const HOST_FILE_BUFFER_SIZE: usize = 4096;
+
+// Specifies the name of the function that should be invoked by the bootloader when it hands
+// control to this code, and the configuration the bootloader should use when loading the kernel.
+// The function name is arbitrary.
+bootloader_api::entry_point!(simpleos_main, config = &boot_config::BOOTLOADER_CONFIG);
+
+/// The bootloader invokes this function at the end of its boot process when it is ready to hand
+/// control to the kernel. This implementation reports the bootloader configuration and the files
+/// available through fw_cfg, then loops forever.
+fn simpleos_main(bootinfo: &'static mut bootloader_api::BootInfo) -> ! {
+    boot_config::log_and_validate(bootinfo);
+    show_fw_cfg_files();
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
+
+/// Lists the files in QEMU's fw_cfg file directory, then outputs the contents of those passed by
+/// the host. Files that are not valid UTF-8 are summarized rather than output.
+fn show_fw_cfg_files() {
+    let mut fw_cfg = FW_CFG.lock();
+
+    if !fw_cfg.probe() {
+        println!("QEMU fw_cfg interface not found");
+        return;
+    }
+
+    let transfer = if fw_cfg.dma_supported() {
+        "DMA"
+    } else {
+        "I/O port"
+    };
+    println!("QEMU fw_cfg files (read using {transfer}):");
+
+    let mut host_files: [Option<FwCfgFile>; MAX_HOST_FILES] = [None; MAX_HOST_FILES];
+    let mut host_file_count = 0;
+    fw_cfg.for_each_file(|file| {
+        println!("{:>10} {}", file.size(), file.name());
+
+        if file.name().starts_with(HOST_FILE_PREFIX) && host_file_count < MAX_HOST_FILES {
+            host_files[host_file_count] = Some(*file);
+            host_file_count += 1;
+        }
+    });
+
+    let mut buf = [0; HOST_FILE_BUFFER_SIZE];
+    for file in host_files.iter().flatten() {
+        let len = fw_cfg.read_file(file, &mut buf);
+        println!("\nContents of {}:", file.name());
+        match core::str::from_utf8(&buf[..len]) {
+            Ok(text) => println!("{text}"),
+            Err(_) => println!("<{} bytes of binary data>", file.size()),
+        }
+    }
+}
+
+/// Rust requires a function with the "panic_handler" attribute [1] to be defined. This is usually
+/// called if a panic occurs, except that this is overridden by the `panic = "abort"` lines in
+/// Cargo.toml in this project to keep things simple. The function name is arbirary as only the
+/// attribute is used to identify which function should be called.
+///
+/// This function prints a message indicating that the kernel has panicked and the debug output
+/// of the `PanicInfo` object passed, which includes the panic message and the line of code where
+/// the panic occurred.
+///
+/// [1]: https://doc.rust-lang.org/reference/runtime.html#the-panic_handler-attribute
+#[panic_handler]
+fn panic(panic_info: &PanicInfo) -> ! {
+    println!("\nKERNEL PANIC");
+    println!("{panic_info:#?}");
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
@@ -0,0 +1,117 @@
+/// Adds UEFI information to a kernel file to make it bootable via UEFI.
+///
+/// The kernel source needs to be compiled before it can be made bootable and this must be done
+/// using Cargo's binary artifact dependency functionality so that its location is set in an
+/// environment variable before this file is built. The UEFI-enabled kernel is saved in the same
+/// directory as the kernel object and has the same name with "_uefi" appended.
+///
+/// The disk image also contains a boot configuration which the bootloader reads before loading
+/// the kernel. This is used to request a minimum framebuffer resolution.
+///
+/// Files on the host can be made available to the kernel by passing one or more `--fw-cfg FILE`
+/// options, e.g., `cargo run -p add_uefi_boot -- --fw-cfg test.txt`. Each is passed to QEMU's
+/// fw_cfg interface with a name consisting of "opt/simpleos/" followed by the file's name.
+use bootloader::{BootConfig, UefiBoot};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+const UEFI_EXTENSION: &str = "_uefi";
+const UEFI_FIRMWARE_PATH: &str = "/usr/share/ovmf/OVMF.fd"; // Set to location of OVMF firmware
+
+// Minimum framebuffer resolution requested from the bootloader. These must match the constants of
+// the same names in the kernel's src/boot_config.rs, which checks the resolution provided.
+const MINIMUM_FRAMEBUFFER_WIDTH: u64 = 1024;
+const MINIMUM_FRAMEBUFFER_HEIGHT: u64 = 768;
+
+// The option used to pass host files to the kernel, and the prefix and maximum length of the fw_cfg
+// names given to them. The prefix must match `HOST_FILE_PREFIX` in the kernel's src/fw_cfg.rs.
+// QEMU reserves names that don't start with "opt/" for its own use.
+const FW_CFG_OPTION: &str = "--fw-cfg";
+const FW_CFG_FILE_PREFIX: &str = "opt/simpleos/";
+const FW_CFG_MAX_NAME_LEN: usize = 55;
+
+fn main() {
+    let fw_cfg_files = parse_args();
+
+    let kernel_path_env: &'static str = env!("CARGO_BIN_FILE_KERNEL_kernel");
+    let uefi_kernel_path = [kernel_path_env, UEFI_EXTENSION].concat();
+    let kernel_path = Path::new(kernel_path_env);
+    let mut boot_config = BootConfig::default();
+    boot_config.frame_buffer.minimum_framebuffer_width = Some(MINIMUM_FRAMEBUFFER_WIDTH);
+    boot_config.frame_buffer.minimum_framebuffer_height = Some(MINIMUM_FRAMEBUFFER_HEIGHT);
+
+    let mut uefi_boot = UefiBoot::new(kernel_path);
+    uefi_boot.set_boot_config(&boot_config);
+    let bootable_kernel_path = Path::new(&uefi_kernel_path);
+
+    uefi_boot
+        .create_disk_image(bootable_kernel_path)
+        .expect("Failed to create a UEFI-enabled version of your kernel image");
+
+    let mut cmd = Command::new("qemu-system-x86_64");
+    cmd.arg("-bios").arg(UEFI_FIRMWARE_PATH);
+    cmd.arg("-drive").arg(format!(
+        "file={},format=raw,index=0,media=disk",
+        bootable_kernel_path.display()
+    ));
+    cmd.arg("-debugcon").arg("stdio"); // Pass data sent to QEMU debugging console to host's stdio
+
+    for file in &fw_cfg_files {
+        cmd.arg("-fw_cfg").arg(fw_cfg_arg(file));
+    }
+
+    let mut child = cmd
+        .spawn()
+        .expect("Failed to run 'qemu' on the bootable kernel image");
+    child
+        .wait()
+        .expect("qemu terminated with an exit status indicating a failure");
+}
+
+/// Returns the paths of the files passed with `--fw-cfg` options. Prints a usage message and exits
+/// if any other argument is passed.
+fn parse_args() -> Vec<PathBuf> {
+    let mut fw_cfg_files = Vec::new();
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            (FW_CFG_OPTION, Some(file)) => fw_cfg_files.push(PathBuf::from(file)),
+            _ => {
+                eprintln!("Usage: cargo run -p add_uefi_boot -- [{FW_CFG_OPTION} FILE]...");
+                process::exit(1);
+            }
+        }
+    }
+
+    fw_cfg_files
+}
+
+/// Returns the value of the QEMU `-fw_cfg` argument that passes `file` to the kernel. Exits if
+/// `file` has no file name, or the resulting fw_cfg name is too long for QEMU's file directory.
+fn fw_cfg_arg(file: &Path) -> String {
+    let Some(file_name) = file.file_name() else {
+        eprintln!("'{}' is not a file", file.display());
+        process::exit(1);
+    };
+    let name = format!("{FW_CFG_FILE_PREFIX}{}", file_name.to_string_lossy());
+
+    if name.len() > FW_CFG_MAX_NAME_LEN {
+        eprintln!("The fw_cfg name '{name}' is longer than {FW_CFG_MAX_NAME_LEN} characters");
+        process::exit(1);
+    }
+
+    format!(
+        "name={},file={}",
+        escape_option_value(&name),
+        escape_option_value(&file.display().to_string())
+    )
+}
+
+/// Returns `value` with each comma doubled, which QEMU requires for a comma in the value of a
+/// suboption, e.g., a path in `-fw_cfg name=NAME,file=PATH`. Otherwise the comma would end the
+/// value, and the rest would be parsed as another suboption.
+fn escape_option_value(value: &str) -> String {
+    value.replace(',', ",,")
+}
@@ -0,0 +1,159 @@
+//! Typed handles for reading and writing x86 I/O ports.
+//!
+//! Accessing an arbitrary I/O port can put a device into a state that corrupts memory, so creating
+//! a handle is `unsafe`, but reading and writing through one is not. Each device's module creates
+//! the handles for its ports once, and the rest of the device's code then uses them without any
+//! `unsafe` blocks. Handles are created for a single port with `Port::new()`, or for a device
+//! whose ports are consecutive with `PortBlock::port()`. `LockedPort` wraps a `Port` in a `Mutex`
+//! for ports that are shared by several parts of the kernel.
+//!
+//! If the kernel is built with the "trace-port-io" feature, every access is output to QEMU's
+//! debugging console, except accesses to the console's own port.
+
+use core::mem::size_of;
+use spin::Mutex;
+use x86_64::instructions::port::{
+    PortGeneric, PortRead, PortReadAccess, PortWrite, PortWriteAccess, ReadWriteAccess,
+};
+
+#[cfg(feature = "trace-port-io")]
+use crate::println;
+
+/// A handle for a single I/O port, which transfers values of type `T` (`u8`, `u16` or `u32`).
+/// `A` is one of `ReadOnlyAccess`, `WriteOnlyAccess` or `ReadWriteAccess` from the `x86_64`
+/// crate's `port` module, and determines which of `read()` and `write()` are available.
+pub struct Port<T, A = ReadWriteAccess> {
+    port: PortGeneric<T, A>,
+    #[cfg(feature = "trace-port-io")]
+    number: u16,
+    #[cfg(feature = "trace-port-io")]
+    traced: bool,
+}
+
+impl<T, A> Port<T, A> {
+    /// Creates a handle for the port with the given number.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the port belongs to a device whose accesses can't violate
+    /// memory safety, and that no other code accesses the port in a way that interferes with the
+    /// use of this handle.
+    pub const unsafe fn new(number: u16) -> Self {
+        Self {
+            port: PortGeneric::new(number),
+            #[cfg(feature = "trace-port-io")]
+            number,
+            #[cfg(feature = "trace-port-io")]
+            traced: true,
+        }
+    }
+
+    /// Creates a handle in the same way as `new()`, except that accesses are never traced. This
+    /// is only needed for the port that trace output is written to.
+    ///
+    /// # Safety
+    ///
+    /// As for `new()`.
+    pub const unsafe fn new_untraced(number: u16) -> Self {
+        Self {
+            port: PortGeneric::new(number),
+            #[cfg(feature = "trace-port-io")]
+            number,
+            #[cfg(feature = "trace-port-io")]
+            traced: false,
+        }
+    }
+
+    #[cfg(feature = "trace-port-io")]
+    fn trace(&self, direction: &str, value: u32) {
+        if self.traced {
+            println!("port {:#06x} {direction} {value:#x}", self.number);
+        }
+    }
+
+    #[cfg(not(feature = "trace-port-io"))]
+    fn trace(&self, _direction: &str, _value: u32) {}
+}
+
+impl<T: PortRead + Into<u32> + Copy, A: PortReadAccess> Port<T, A> {
+    /// Reads a value from the port.
+    pub fn read(&mut self) -> T {
+        // The caller of `new()` guaranteed that accessing the port is safe.
+        let value = unsafe { self.port.read() };
+        self.trace("->", value.into());
+        value
+    }
+}
+
+impl<T: PortWrite + Into<u32> + Copy, A: PortWriteAccess> Port<T, A> {
+    /// Writes a value to the port.
+    pub fn write(&mut self, value: T) {
+        self.trace("<-", value.into());
+        // The caller of `new()` guaranteed that accessing the port is safe.
+        unsafe {
+            self.port.write(value);
+        }
+    }
+}
+
+/// A handle for a single I/O port that is protected against multiple accesses by a
+/// spinlock-based `Mutex`, so it can be shared through a `static`.
+pub struct LockedPort<T, A = ReadWriteAccess> {
+    port: Mutex<Port<T, A>>,
+}
+
+impl<T, A> LockedPort<T, A> {
+    /// Wraps an existing handle so it can be shared.
+    pub const fn new(port: Port<T, A>) -> Self {
+        Self {
+            port: Mutex::new(port),
+        }
+    }
+}
+
+impl<T: PortRead + Into<u32> + Copy, A: PortReadAccess> LockedPort<T, A> {
+    /// Reads a value from the port, holding the lock for the duration of the read.
+    #[allow(dead_code)] // No shared port is read yet
+    pub fn read(&self) -> T {
+        self.port.lock().read()
+    }
+}
+
+impl<T: PortWrite + Into<u32> + Copy, A: PortWriteAccess> LockedPort<T, A> {
+    /// Writes a value to the port, holding the lock for the duration of the write.
+    pub fn write(&self, value: T) {
+        self.port.lock().write(value);
+    }
+}
+
+/// A block of consecutive I/O ports that belong to a single device, e.g., the PIT's ports 0x40 to
+/// 0x43. Handles for individual ports are created from the offset of each port within the block.
+pub struct PortBlock {
+    base: u16,
+    len: u16,
+}
+
+impl PortBlock {
+    /// Creates a block of `len` ports starting at `base`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that every port in the block can be accessed in the way described
+    /// for `Port::new()`.
+    pub const unsafe fn new(base: u16, len: u16) -> Self {
+        Self { base, len }
+    }
+
+    /// Returns a handle for the port at `offset` from the start of the block.
+    ///
+    /// Panics if a value of type `T` at `offset` does not lie entirely within the block.
+    pub const fn port<T, A>(&self, offset: u16) -> Port<T, A> {
+        assert!(
+            offset as usize + size_of::<T>() <= self.len as usize,
+            "port is outside the port block"
+        );
+
+        // The caller of `new()` guaranteed that every port in the block is safe to access.
+        unsafe { Port::new(self.base + offset) }
+    }
+}
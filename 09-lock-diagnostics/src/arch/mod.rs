@@ -0,0 +1,3 @@
+//! Code specific to the x86_64 architecture.
+
+pub mod portio;
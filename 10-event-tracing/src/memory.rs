@@ -0,0 +1,25 @@
+//! Helpers for working with the kernel's virtual and physical address spaces.
+
+use crate::boot_config::PHYSICAL_MEMORY_OFFSET;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{OffsetPageTable, PageTable, Translate};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// Returns the physical address that the given virtual address is mapped to by the active page
+/// tables, or `None` if the address is not mapped.
+///
+/// The page tables are walked through the mapping of physical memory that the bootloader creates
+/// at `PHYSICAL_MEMORY_OFFSET`, so this relies on `boot_config::log_and_validate()` having
+/// confirmed that the mapping exists.
+pub fn translate_addr(addr: VirtAddr) -> Option<PhysAddr> {
+    let (level_4_frame, _) = Cr3::read();
+    let offset = VirtAddr::new(PHYSICAL_MEMORY_OFFSET);
+    let level_4_table_ptr: *mut PageTable =
+        (offset + level_4_frame.start_address().as_u64()).as_mut_ptr();
+
+    // All of physical memory is mapped at `offset`, so `level_4_table_ptr` points to the
+    // active level 4 page table. The table is only read, and nothing else modifies page tables
+    // while this function runs.
+    let page_table = unsafe { OffsetPageTable::new(&mut *level_4_table_ptr, offset) };
+    page_table.translate_addr(addr)
+}
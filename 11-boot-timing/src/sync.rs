@@ -0,0 +1,120 @@
+//! A spinlock-based `Mutex` that can detect deadlocks.
+//!
+//! This wraps `spin::Mutex` and is used in its place throughout the kernel. If the kernel is built
+//! with the "lock-diagnostics" feature, each lock records where it was last acquired and by which
+//! CPU, and a call to `lock()` that spins for longer than `SPIN_LIMIT_CYCLES` panics with both the
+//! location that holds the lock and the location waiting for it. Without a check like this, a
+//! deadlock just causes the kernel to hang silently. Without the feature, `lock()` is identical to
+//! `spin::Mutex::lock()`.
+
+pub use spin::MutexGuard;
+
+#[cfg(feature = "lock-diagnostics")]
+use core::arch::x86_64::{__cpuid, _rdtsc};
+#[cfg(feature = "lock-diagnostics")]
+use core::fmt::Display;
+#[cfg(feature = "lock-diagnostics")]
+use core::hint::spin_loop;
+#[cfg(feature = "lock-diagnostics")]
+use core::panic::Location;
+#[cfg(feature = "lock-diagnostics")]
+use core::ptr;
+#[cfg(feature = "lock-diagnostics")]
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+
+/// The number of time stamp counter cycles `lock()` spins for before assuming a deadlock. This is
+/// a few seconds on current CPUs, which is far longer than any lock in the kernel is held for.
+#[cfg(feature = "lock-diagnostics")]
+const SPIN_LIMIT_CYCLES: u64 = 1 << 33;
+
+// CPUID leaf and bit position of the initial APIC ID, which identifies the CPU executing CPUID.
+#[cfg(feature = "lock-diagnostics")]
+const CPUID_FEATURES_LEAF: u32 = 1;
+#[cfg(feature = "lock-diagnostics")]
+const CPUID_FEATURES_EBX_APIC_ID_SHIFT: u32 = 24;
+
+pub struct Mutex<T> {
+    inner: spin::Mutex<T>,
+    #[cfg(feature = "lock-diagnostics")]
+    holder_location: AtomicPtr<Location<'static>>,
+    #[cfg(feature = "lock-diagnostics")]
+    holder_cpu: AtomicU32,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: spin::Mutex::new(value),
+            #[cfg(feature = "lock-diagnostics")]
+            holder_location: AtomicPtr::new(ptr::null_mut()),
+            #[cfg(feature = "lock-diagnostics")]
+            holder_cpu: AtomicU32::new(0),
+        }
+    }
+
+    /// Acquires the lock, spinning until it is available.
+    #[cfg(not(feature = "lock-diagnostics"))]
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.inner.lock()
+    }
+
+    /// Acquires the lock, spinning until it is available, and records the caller's location and
+    /// CPU.
+    ///
+    /// Panics if the lock is not acquired within `SPIN_LIMIT_CYCLES`.
+    #[cfg(feature = "lock-diagnostics")]
+    #[track_caller]
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let start = unsafe { _rdtsc() };
+
+        let guard = loop {
+            if let Some(guard) = self.inner.try_lock() {
+                break guard;
+            }
+
+            let spun = unsafe { _rdtsc() }.wrapping_sub(start);
+            if spun > SPIN_LIMIT_CYCLES {
+                // The holder records its location just after acquiring the lock, so it is null if
+                // the lock's first holder hasn't yet done so. Locations passed by `#[track_caller]`
+                // are `'static`, so a non-null one is always valid.
+                let holder: &dyn Display =
+                    match unsafe { self.holder_location.load(Ordering::Acquire).as_ref() } {
+                        Some(location) => location,
+                        None => &"unknown location",
+                    };
+                panic!(
+                    "Deadlock: waited {spun} cycles at {} on CPU {}, for a lock held since {} on CPU {}",
+                    Location::caller(),
+                    current_cpu(),
+                    holder,
+                    self.holder_cpu.load(Ordering::Relaxed),
+                );
+            }
+
+            spin_loop();
+        };
+
+        self.holder_location.store(
+            ptr::from_ref(Location::caller()).cast_mut(),
+            Ordering::Release,
+        );
+        self.holder_cpu.store(current_cpu(), Ordering::Relaxed);
+        guard
+    }
+
+    /// Releases the lock, even though it is held.
+    ///
+    /// # Safety
+    ///
+    /// This must only be used when the holder of the lock will never release it, e.g., in the panic
+    /// handler, where the code holding the lock will never run again.
+    pub unsafe fn force_unlock(&self) {
+        unsafe { self.inner.force_unlock() }
+    }
+}
+
+/// Returns the initial APIC ID of the CPU executing this function.
+#[cfg(feature = "lock-diagnostics")]
+fn current_cpu() -> u32 {
+    __cpuid(CPUID_FEATURES_LEAF).ebx >> CPUID_FEATURES_EBX_APIC_ID_SHIFT
+}
@@ -0,0 +1,110 @@
+//! Tests the physical memory that the bootloader reports as usable.
+//!
+//! Nothing in the kernel uses this memory yet, so its contents can be overwritten. Each usable
+//! region is accessed through the bootloader's mapping of all physical memory at
+//! `PHYSICAL_MEMORY_OFFSET`, and tested in two ways:
+//!
+//! - A walking-ones test writes each of the 64 single-bit values to the first word of the region,
+//!   and checks each reads back correctly. This finds data lines that are stuck or shorted
+//!   together.
+//! - An address-in-address test writes every word of the region with its own physical address,
+//!   then checks them all, then repeats this with the complement of each address. This finds
+//!   address lines that are stuck or shorted together, which cause writes to one address to
+//!   overwrite another, and bits that can't hold both values.
+//!
+//! The tests are based on those described by Michael Barr at
+//! <https://barrgroup.com/blog/fast-accurate-memory-test-suite>.
+
+use crate::boot_config::PHYSICAL_MEMORY_OFFSET;
+use crate::println;
+use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
+use core::arch::x86_64::_rdtsc;
+use core::mem::size_of;
+use core::ptr;
+
+const WORD_SIZE: u64 = size_of::<u64>() as u64;
+
+/// The maximum number of miscompares that are output. Any further ones are only counted.
+const MAX_REPORTED_ERRORS: u64 = 8;
+
+/// The results of testing memory.
+pub struct TestResult {
+    pub bytes_tested: u64,
+    pub cycles: u64,
+    pub errors: u64,
+}
+
+/// Tests every region in `memory_regions` that is usable, and returns the results. Each
+/// miscompare is output as it is found, up to `MAX_REPORTED_ERRORS`.
+pub fn run(memory_regions: &MemoryRegions) -> TestResult {
+    let start = unsafe { _rdtsc() };
+    let mut result = TestResult {
+        bytes_tested: 0,
+        cycles: 0,
+        errors: 0,
+    };
+
+    for region in memory_regions
+        .iter()
+        .filter(|region| region.kind == MemoryRegionKind::Usable)
+    {
+        // Only whole words are tested.
+        let start = region.start.next_multiple_of(WORD_SIZE);
+        let end = region.end & !(WORD_SIZE - 1);
+        if start >= end {
+            continue;
+        }
+
+        walking_ones(start, &mut result.errors);
+        address_in_address(start, end, false, &mut result.errors);
+        address_in_address(start, end, true, &mut result.errors);
+        result.bytes_tested += end - start;
+    }
+
+    result.cycles = unsafe { _rdtsc() } - start;
+    result
+}
+
+/// Writes each single-bit value to the word at physical address `addr`, checking each.
+fn walking_ones(addr: u64, errors: &mut u64) {
+    for bit in 0..u64::BITS {
+        let pattern = 1 << bit;
+        write_word(addr, pattern);
+        check_word(addr, pattern, errors);
+    }
+}
+
+/// Writes every word from physical address `start` up to `end` with its own address, or the
+/// complement of its address if `complement` is `true`, then checks every word.
+fn address_in_address(start: u64, end: u64, complement: bool, errors: &mut u64) {
+    let pattern = |addr: u64| if complement { !addr } else { addr };
+
+    for addr in (start..end).step_by(WORD_SIZE as usize) {
+        write_word(addr, pattern(addr));
+    }
+
+    for addr in (start..end).step_by(WORD_SIZE as usize) {
+        check_word(addr, pattern(addr), errors);
+    }
+}
+
+fn write_word(addr: u64, value: u64) {
+    // Physical memory is mapped at `PHYSICAL_MEMORY_OFFSET`, and the caller only passes addresses
+    // in usable regions, which nothing else in the kernel uses.
+    unsafe {
+        ptr::write_volatile((PHYSICAL_MEMORY_OFFSET + addr) as *mut u64, value);
+    }
+}
+
+/// Reads the word at physical address `addr` and counts a miscompare if it isn't `expected`.
+fn check_word(addr: u64, expected: u64, errors: &mut u64) {
+    // As for `write_word()`.
+    let actual = unsafe { ptr::read_volatile((PHYSICAL_MEMORY_OFFSET + addr) as *const u64) };
+
+    if actual != expected {
+        if *errors < MAX_REPORTED_ERRORS {
+            println!("Memory error at {addr:#x}: wrote {expected:#018x}, read {actual:#018x}");
+        }
+        *errors += 1;
+    }
+}
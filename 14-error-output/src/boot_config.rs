@@ -0,0 +1,95 @@
+//! Configures how the bootloader sets up the environment the kernel runs in, and checks that the
+//! bootloader honoured the configuration.
+
+use crate::{eprintln, println};
+use bootloader_api::config::{BootloaderConfig, Mapping};
+use bootloader_api::BootInfo;
+
+/// Virtual address at which the bootloader is asked to map the whole of physical memory. Adding
+/// this offset to any physical address gives a virtual address the kernel can use to access it.
+pub const PHYSICAL_MEMORY_OFFSET: u64 = 0x0000_4000_0000_0000;
+
+/// Size of the kernel stack requested from the bootloader, which otherwise defaults to 80 KiB.
+pub const KERNEL_STACK_SIZE: u64 = 256 * 1024;
+
+/// Minimum framebuffer width requested, in pixels. The bootloader reads this from the boot
+/// configuration stored in the disk image by `add_uefi_boot`, not from `BOOTLOADER_CONFIG`, so this
+/// value must match `MINIMUM_FRAMEBUFFER_WIDTH` in add_uefi_boot/src/main.rs.
+pub const MINIMUM_FRAMEBUFFER_WIDTH: usize = 1024;
+
+/// Minimum framebuffer height requested, in pixels. This must match `MINIMUM_FRAMEBUFFER_HEIGHT`
+/// in add_uefi_boot/src/main.rs.
+pub const MINIMUM_FRAMEBUFFER_HEIGHT: usize = 768;
+
+/// The configuration passed to the bootloader. The `entry_point!` macro serializes this into a
+/// dedicated section of the kernel executable, where the bootloader finds it when loading the
+/// kernel.
+pub static BOOTLOADER_CONFIG: BootloaderConfig = {
+    let mut config = BootloaderConfig::new_default();
+    config.mappings.physical_memory = Some(Mapping::FixedAddress(PHYSICAL_MEMORY_OFFSET));
+    config.kernel_stack_size = KERNEL_STACK_SIZE;
+    config
+};
+
+/// Outputs each configuration value requested from the bootloader alongside the value the
+/// bootloader actually provided, then checks them.
+///
+/// Panics if physical memory is not mapped at the requested offset or the kernel stack is smaller
+/// than requested, as later code relies on both. A smaller framebuffer is only reported because
+/// the bootloader is permitted to fall back to a lower resolution if the requested one is not
+/// available.
+pub fn log_and_validate(boot_info: &BootInfo) {
+    let api = &boot_info.api_version;
+    println!(
+        "Bootloader API version {}.{}.{}",
+        api.version_major(),
+        api.version_minor(),
+        api.version_patch()
+    );
+
+    let physical_memory_offset = boot_info.physical_memory_offset.into_option();
+    match physical_memory_offset {
+        Some(offset) => println!(
+            "Physical memory offset: requested {PHYSICAL_MEMORY_OFFSET:#x}, provided {offset:#x}"
+        ),
+        None => {
+            println!("Physical memory offset: requested {PHYSICAL_MEMORY_OFFSET:#x}, not mapped")
+        }
+    }
+
+    println!(
+        "Kernel stack size: requested {KERNEL_STACK_SIZE} bytes, provided {} bytes at {:#x}",
+        boot_info.kernel_stack_len, boot_info.kernel_stack_bottom
+    );
+
+    let framebuffer_size = boot_info
+        .framebuffer
+        .as_ref()
+        .map(|fb| (fb.info().width, fb.info().height));
+    match framebuffer_size {
+        Some((width, height)) => println!(
+            "Framebuffer: requested at least {MINIMUM_FRAMEBUFFER_WIDTH}x\
+            {MINIMUM_FRAMEBUFFER_HEIGHT}, provided {width}x{height}"
+        ),
+        None => println!(
+            "Framebuffer: requested at least {MINIMUM_FRAMEBUFFER_WIDTH}x\
+            {MINIMUM_FRAMEBUFFER_HEIGHT}, none provided"
+        ),
+    }
+
+    assert_eq!(
+        physical_memory_offset,
+        Some(PHYSICAL_MEMORY_OFFSET),
+        "bootloader did not map physical memory at the requested offset"
+    );
+    assert!(
+        boot_info.kernel_stack_len >= KERNEL_STACK_SIZE,
+        "bootloader provided a smaller kernel stack than requested"
+    );
+
+    if let Some((width, height)) = framebuffer_size {
+        if width < MINIMUM_FRAMEBUFFER_WIDTH || height < MINIMUM_FRAMEBUFFER_HEIGHT {
+            eprintln!("Framebuffer is smaller than requested");
+        }
+    }
+}
@@ -0,0 +1,229 @@
+/// Adds UEFI information to a kernel file to make it bootable via UEFI.
+///
+/// The kernel source needs to be compiled before it can be made bootable and this must be done
+/// using Cargo's binary artifact dependency functionality so that its location is set in an
+/// environment variable before this file is built. The UEFI-enabled kernel is saved in the same
+/// directory as the kernel object and has the same name with "_uefi" appended.
+///
+/// The disk image also contains a boot configuration which the bootloader reads before loading
+/// the kernel. This is used to request a minimum framebuffer resolution.
+///
+/// Files on the host can be made available to the kernel by passing one or more `--fw-cfg FILE`
+/// options, e.g., `cargo run -p add_uefi_boot -- --fw-cfg test.txt`. Each is passed to QEMU's
+/// fw_cfg interface with a name consisting of "opt/simpleos/" followed by the file's name.
+///
+/// The kernel reports the time taken by each stage of boot. Passing `--boot-report FILE` saves
+/// these lines of the kernel's output to FILE, so they can be compared with those of other runs.
+///
+/// The kernel's output is sent to this program's stdout by default. Passing `--debugcon FILE`
+/// saves it to FILE instead. The kernel also sends its error messages to the serial port COM1,
+/// and passing `--serial FILE` saves these to FILE, which separates them from the rest of the
+/// output, e.g., for checking in automated tests.
+use bootloader::{BootConfig, UefiBoot};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+const UEFI_EXTENSION: &str = "_uefi";
+const UEFI_FIRMWARE_PATH: &str = "/usr/share/ovmf/OVMF.fd"; // Set to location of OVMF firmware
+
+// Minimum framebuffer resolution requested from the bootloader. These must match the constants of
+// the same names in the kernel's src/boot_config.rs, which checks the resolution provided.
+const MINIMUM_FRAMEBUFFER_WIDTH: u64 = 1024;
+const MINIMUM_FRAMEBUFFER_HEIGHT: u64 = 768;
+
+// The option used to pass host files to the kernel, and the prefix and maximum length of the fw_cfg
+// names given to them. The prefix must match `HOST_FILE_PREFIX` in the kernel's src/fw_cfg.rs.
+// QEMU reserves names that don't start with "opt/" for its own use.
+const FW_CFG_OPTION: &str = "--fw-cfg";
+const FW_CFG_FILE_PREFIX: &str = "opt/simpleos/";
+const FW_CFG_MAX_NAME_LEN: usize = 55;
+
+// The option used to save the kernel's boot time report to a file, and the text at the start of
+// each line of the report. The prefix must match `REPORT_PREFIX` in the kernel's
+// src/boot_timing.rs.
+const BOOT_REPORT_OPTION: &str = "--boot-report";
+const BOOT_REPORT_PREFIX: &str = "boot-time:";
+
+// The options used to save the output of QEMU's debugging console and of the serial port COM1 to
+// files.
+const DEBUGCON_OPTION: &str = "--debugcon";
+const SERIAL_OPTION: &str = "--serial";
+
+/// The options passed on the command line.
+struct Args {
+    fw_cfg_files: Vec<PathBuf>,
+    boot_report: Option<PathBuf>,
+    debugcon: Option<PathBuf>,
+    serial: Option<PathBuf>,
+}
+
+fn main() {
+    let args = parse_args();
+
+    let kernel_path_env: &'static str = env!("CARGO_BIN_FILE_KERNEL_kernel");
+    let uefi_kernel_path = [kernel_path_env, UEFI_EXTENSION].concat();
+    let kernel_path = Path::new(kernel_path_env);
+    let mut boot_config = BootConfig::default();
+    boot_config.frame_buffer.minimum_framebuffer_width = Some(MINIMUM_FRAMEBUFFER_WIDTH);
+    boot_config.frame_buffer.minimum_framebuffer_height = Some(MINIMUM_FRAMEBUFFER_HEIGHT);
+
+    let mut uefi_boot = UefiBoot::new(kernel_path);
+    uefi_boot.set_boot_config(&boot_config);
+    let bootable_kernel_path = Path::new(&uefi_kernel_path);
+
+    uefi_boot
+        .create_disk_image(bootable_kernel_path)
+        .expect("Failed to create a UEFI-enabled version of your kernel image");
+
+    let mut cmd = Command::new("qemu-system-x86_64");
+    cmd.arg("-bios").arg(UEFI_FIRMWARE_PATH);
+    cmd.arg("-drive").arg(format!(
+        "file={},format=raw,index=0,media=disk",
+        bootable_kernel_path.display()
+    ));
+    let debugcon = add_chardev(&mut cmd, "debugcon", args.debugcon.as_deref());
+    cmd.arg("-debugcon").arg(debugcon); // Pass data sent to QEMU debugging console to stdio or a file
+
+    if let Some(serial) = &args.serial {
+        let serial = add_chardev(&mut cmd, "serial", Some(serial));
+        cmd.arg("-serial").arg(serial);
+    }
+
+    for file in &args.fw_cfg_files {
+        cmd.arg("-fw_cfg").arg(fw_cfg_arg(file));
+    }
+
+    // The report is created before QEMU is started, so that QEMU isn't left running if the report
+    // can't be created.
+    let report = args.boot_report.as_ref().map(|report_path| {
+        cmd.stdout(Stdio::piped());
+        File::create(report_path).unwrap_or_else(|e| {
+            eprintln!("Failed to create '{}': {e}", report_path.display());
+            process::exit(1);
+        })
+    });
+
+    let mut child = cmd
+        .spawn()
+        .expect("Failed to run 'qemu' on the bootable kernel image");
+
+    if let Some(report) = report {
+        let stdout = child.stdout.take().expect("qemu's stdout was not captured");
+        save_boot_report(stdout, report);
+    }
+
+    child
+        .wait()
+        .expect("qemu terminated with an exit status indicating a failure");
+}
+
+/// Returns the options passed on the command line. Prints a usage message and exits if an
+/// unrecognized argument is passed.
+fn parse_args() -> Args {
+    let mut parsed = Args {
+        fw_cfg_files: Vec::new(),
+        boot_report: None,
+        debugcon: None,
+        serial: None,
+    };
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            (FW_CFG_OPTION, Some(file)) => parsed.fw_cfg_files.push(PathBuf::from(file)),
+            (BOOT_REPORT_OPTION, Some(file)) => parsed.boot_report = Some(PathBuf::from(file)),
+            (DEBUGCON_OPTION, Some(file)) => parsed.debugcon = Some(PathBuf::from(file)),
+            (SERIAL_OPTION, Some(file)) => parsed.serial = Some(PathBuf::from(file)),
+            _ => {
+                eprintln!(
+                    "Usage: cargo run -p add_uefi_boot -- [{FW_CFG_OPTION} FILE]... \
+                     [{BOOT_REPORT_OPTION} FILE] [{DEBUGCON_OPTION} FILE] [{SERIAL_OPTION} FILE]"
+                );
+                process::exit(1);
+            }
+        }
+    }
+
+    // The boot time report is extracted from the debugging console's output on stdout.
+    if parsed.boot_report.is_some() && parsed.debugcon.is_some() {
+        eprintln!("{BOOT_REPORT_OPTION} can't be used with {DEBUGCON_OPTION}");
+        process::exit(1);
+    }
+
+    parsed
+}
+
+/// Copies `qemu_output` to stdout until QEMU exits, and writes the lines of the kernel's boot time
+/// report to `report`. Each line is written as soon as it is output, because the kernel never
+/// exits, so QEMU only exits when it is closed by the user. Bytes that aren't valid UTF-8 are
+/// replaced, as the kernel can send anything to the debugging console.
+fn save_boot_report(qemu_output: impl io::Read, mut report: File) {
+    let mut qemu_output = BufReader::new(qemu_output);
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        let len = qemu_output
+            .read_until(b'\n', &mut buf)
+            .expect("Failed to read qemu's output");
+        if len == 0 {
+            break;
+        }
+
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\r', '\n']);
+        println!("{line}");
+
+        if line.starts_with(BOOT_REPORT_PREFIX) {
+            writeln!(report, "{line}")
+                .and_then(|()| report.flush())
+                .expect("Failed to write the boot time report");
+        }
+    }
+}
+
+/// Adds a QEMU character device named `id` to `cmd`, which writes to `file`, or to stdio if `file`
+/// is `None`. Returns the value of a QEMU argument, e.g., `-serial`, that sends a device's output
+/// to it. The device is defined with `-chardev`, as the shorter `file:` form can't escape the path.
+fn add_chardev(cmd: &mut Command, id: &str, file: Option<&Path>) -> String {
+    let backend = match file {
+        Some(file) => format!(
+            "file,path={}",
+            escape_option_value(&file.display().to_string())
+        ),
+        None => String::from("stdio"),
+    };
+    cmd.arg("-chardev").arg(format!("{backend},id={id}"));
+    format!("chardev:{id}")
+}
+
+/// Returns the value of the QEMU `-fw_cfg` argument that passes `file` to the kernel. Exits if
+/// `file` has no file name, or the resulting fw_cfg name is too long for QEMU's file directory.
+fn fw_cfg_arg(file: &Path) -> String {
+    let Some(file_name) = file.file_name() else {
+        eprintln!("'{}' is not a file", file.display());
+        process::exit(1);
+    };
+    let name = format!("{FW_CFG_FILE_PREFIX}{}", file_name.to_string_lossy());
+
+    if name.len() > FW_CFG_MAX_NAME_LEN {
+        eprintln!("The fw_cfg name '{name}' is longer than {FW_CFG_MAX_NAME_LEN} characters");
+        process::exit(1);
+    }
+
+    format!(
+        "name={},file={}",
+        escape_option_value(&name),
+        escape_option_value(&file.display().to_string())
+    )
+}
+
+/// Returns `value` with each comma doubled, which QEMU requires for a comma in the value of a
+/// suboption, e.g., a path in `-fw_cfg name=NAME,file=PATH`. Otherwise the comma would end the
+/// value, and the rest would be parsed as another suboption.
+fn escape_option_value(value: &str) -> String {
+    value.replace(',', ",,")
+}
@@ -0,0 +1,130 @@
+//! Types that format values in human-readable ways, for use with `print!` and similar macros.
+//!
+//! Each type wraps the value to be formatted and implements `Display`, e.g.,
+//! `println!("{}", ByteSize(4096))` outputs "4.0 KiB". Width and alignment are supported by
+//! `ByteSize` and `HumanDuration`, e.g., `{:>10}`.
+
+use core::fmt::{self, Display, Write};
+use core::time::Duration;
+
+/// The number of bytes shown on each line of a `HexDump`.
+const HEX_DUMP_BYTES_PER_LINE: usize = 16;
+
+/// The maximum length of the text produced by `ByteSize` and `HumanDuration`, before padding.
+const MAX_VALUE_LEN: usize = 32;
+
+/// Displays a byte slice in the same format as `hexdump -C`, i.e., one line per 16 bytes, each
+/// containing the offset of the first byte, the bytes in hexadecimal, and the bytes as ASCII with
+/// unprintable characters shown as '.'. Each line ends with a newline.
+pub struct HexDump<'a>(pub &'a [u8]);
+
+impl Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (line, bytes) in self.0.chunks(HEX_DUMP_BYTES_PER_LINE).enumerate() {
+            write!(f, "{:08x} ", line * HEX_DUMP_BYTES_PER_LINE)?;
+
+            for i in 0..HEX_DUMP_BYTES_PER_LINE {
+                // An extra space separates the two halves of the line.
+                if i == HEX_DUMP_BYTES_PER_LINE / 2 {
+                    f.write_char(' ')?;
+                }
+
+                match bytes.get(i) {
+                    Some(b) => write!(f, " {b:02x}")?,
+                    None => f.write_str("   ")?,
+                }
+            }
+
+            f.write_str("  |")?;
+            for &b in bytes {
+                let c = if b.is_ascii_graphic() || b == b' ' {
+                    char::from(b)
+                } else {
+                    '.'
+                };
+                f.write_char(c)?;
+            }
+            f.write_str("|\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Displays a number of bytes using the largest binary unit in which it is at least 1, with one
+/// decimal place, e.g., "512 B", "1.5 KiB" or "119.0 MiB".
+pub struct ByteSize(pub u64);
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+        if self.0 < 1024 {
+            return pad(f, format_args!("{} B", self.0));
+        }
+
+        let mut unit = 0;
+        while unit + 1 < UNITS.len() && self.0 >> (10 * (unit + 2)) != 0 {
+            unit += 1;
+        }
+
+        // Calculated in tenths of the unit, rounding down, to avoid floating point.
+        let tenths = (u128::from(self.0) * 10) >> (10 * (unit + 1));
+        pad(
+            f,
+            format_args!("{}.{} {}", tenths / 10, tenths % 10, UNITS[unit]),
+        )
+    }
+}
+
+/// Displays a duration using the largest unit in which it is at least 1, with one decimal place
+/// for units other than nanoseconds, e.g., "750 ns", "12.5 µs" or "1.2 s".
+#[allow(dead_code)] // The kernel has no calibrated time source to measure durations with yet
+pub struct HumanDuration(pub Duration);
+
+impl Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [(u128, &str); 3] = [(1_000_000_000, "s"), (1_000_000, "ms"), (1_000, "µs")];
+
+        let nanos = self.0.as_nanos();
+        for (nanos_per_unit, unit) in UNITS {
+            if nanos >= nanos_per_unit {
+                let tenths = nanos * 10 / nanos_per_unit;
+                return pad(f, format_args!("{}.{} {unit}", tenths / 10, tenths % 10));
+            }
+        }
+
+        pad(f, format_args!("{nanos} ns"))
+    }
+}
+
+/// Formats `args` into a buffer, then writes it to `f`, applying the width and alignment that
+/// `f` specifies. `Formatter::pad()` needs the complete text to do this.
+fn pad(f: &mut fmt::Formatter, args: fmt::Arguments) -> fmt::Result {
+    let mut buf = ValueBuffer {
+        bytes: [0; MAX_VALUE_LEN],
+        len: 0,
+    };
+    buf.write_fmt(args)?;
+
+    // Only whole strings are written to the buffer, so it always holds valid UTF-8.
+    f.pad(core::str::from_utf8(&buf.bytes[..buf.len]).map_err(|_| fmt::Error)?)
+}
+
+struct ValueBuffer {
+    bytes: [u8; MAX_VALUE_LEN],
+    len: usize,
+}
+
+impl Write for ValueBuffer {
+    /// Appends `s` to the buffer. Returns an error if the buffer is too small to hold it.
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        let end = self.len + s.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
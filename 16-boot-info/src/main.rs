@@ -0,0 +1,173 @@
+#![no_main] // Prevents the compiler from "emitting the main symbol for an executable binary".
+#![no_std] // Prevents the linking of Rust's standard library.
+
+//! A freestanding kernel based on example code in the `bootloader` and `bootloader_api` crates, and
+//! Philipp Oppermann's blog on writing a kernel in Rust at <https://os.phil-opp.com/>.
+//!
+//! At boot, it checks the configuration provided by the bootloader and, if the "memory-test"
+//! feature is enabled, tests the usable physical memory. It then uses QEMU's fw_cfg interface to
+//! list the files QEMU makes available to the guest. The contents of any files passed by the host
+//! with `add_uefi_boot`'s `--fw-cfg` option are sent to QEMU's debugging console. Finally, it
+//! initializes the random number generator and outputs a few random numbers, then outputs the
+//! events recorded in the trace buffer during boot and the time taken by each stage of boot, and
+//! loops forever.
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use fmtutil::{ByteSize, HexDump};
+use fw_cfg::{FwCfgFile, FW_CFG, HOST_FILE_PREFIX};
+use trace::Category;
+
+mod arch;
+mod boot_config;
+mod boot_timing;
+mod bootinfo;
+mod fmtutil;
+mod fw_cfg;
+mod memory;
+#[cfg(feature = "memory-test")]
+mod memory_test;
+mod qemu_console;
+mod rng;
+mod serial;
+mod sync;
+mod trace;
+
+// The maximum number of files passed by the host that are output, and the number of bytes output
+// from each.
+const MAX_HOST_FILES: usize = 8;
+const HOST_FILE_BUFFER_SIZE: usize = 4096;
+
+// The number of random values and random bytes output at boot.
+const RANDOM_VALUE_COUNT: usize = 4;
+const RANDOM_BYTE_COUNT: usize = 16;
+
+// Specifies the name of the function that should be invoked by the bootloader when it hands
+// control to this code, and the configuration the bootloader should use when loading the kernel.
+// The function name is arbitrary.
+bootloader_api::entry_point!(simpleos_main, config = &boot_config::BOOTLOADER_CONFIG);
+
+/// The bootloader invokes this function at the end of its boot process when it is ready to hand
+/// control to the kernel. This implementation reports the bootloader configuration and the files
+/// available through fw_cfg, outputs some random numbers, the trace buffer and the time taken by
+/// each stage, then loops forever.
+fn simpleos_main(bootinfo: &'static mut bootloader_api::BootInfo) -> ! {
+    trace_event!(Category::Boot, "Kernel entered");
+    serial::SERIAL.lock().init();
+    let boot_info = bootinfo::BootInfo::from_bootloader(bootinfo);
+    boot_timing::stage("boot_config", || boot_config::log_and_validate(&boot_info));
+    #[cfg(feature = "memory-test")]
+    boot_timing::stage("memory_test", || show_memory_test(&boot_info));
+    boot_timing::stage("fw_cfg", show_fw_cfg_files);
+    boot_timing::stage("rng", show_random_numbers);
+    trace_event!(Category::Boot, "Boot complete");
+
+    println!();
+    trace::dump();
+    println!();
+    boot_timing::report();
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
+
+/// Tests the usable physical memory, then outputs the amount tested, the time taken and the number
+/// of errors found.
+#[cfg(feature = "memory-test")]
+fn show_memory_test(boot_info: &bootinfo::BootInfo) {
+    println!("\nTesting usable memory");
+    let result = memory_test::run(boot_info);
+
+    let mib_tested = result.bytes_tested / (1024 * 1024);
+    println!(
+        "Tested {} in {} cycles ({} cycles per MiB), {} errors",
+        ByteSize(result.bytes_tested),
+        result.cycles,
+        result.cycles / mib_tested.max(1),
+        result.errors
+    );
+}
+
+/// Lists the files in QEMU's fw_cfg file directory, then outputs the contents of those passed by
+/// the host. Files that are not valid UTF-8 are output as a hex dump.
+fn show_fw_cfg_files() {
+    let mut fw_cfg = FW_CFG.lock();
+
+    if !fw_cfg.probe() {
+        eprintln!("QEMU fw_cfg interface not found");
+        return;
+    }
+
+    let transfer = if fw_cfg.dma_supported() {
+        "DMA"
+    } else {
+        "I/O port"
+    };
+    println!("QEMU fw_cfg files (read using {transfer}):");
+
+    let mut host_files: [Option<FwCfgFile>; MAX_HOST_FILES] = [None; MAX_HOST_FILES];
+    let mut host_file_count = 0;
+    fw_cfg.for_each_file(|file| {
+        println!("{:>10} {}", ByteSize(file.size() as u64), file.name());
+
+        if file.name().starts_with(HOST_FILE_PREFIX) && host_file_count < MAX_HOST_FILES {
+            host_files[host_file_count] = Some(*file);
+            host_file_count += 1;
+        }
+    });
+
+    let mut buf = [0; HOST_FILE_BUFFER_SIZE];
+    for file in host_files.iter().flatten() {
+        let len = fw_cfg.read_file(file, &mut buf);
+        println!("\nContents of {}:", file.name());
+        match core::str::from_utf8(&buf[..len]) {
+            Ok(text) => println!("{text}"),
+            Err(_) => print!("{}", HexDump(&buf[..len])),
+        }
+    }
+}
+
+/// Initializes the random number generator, reports the sources of randomness it uses, then
+/// outputs some random values and bytes.
+fn show_random_numbers() {
+    let (rdrand_used, seed_source) = rng::init();
+
+    let generator = if rdrand_used { "RDRAND" } else { "ChaCha20" };
+    println!("\nRandom numbers generated using {generator}, seeded from {seed_source:?}:");
+
+    for _ in 0..RANDOM_VALUE_COUNT {
+        println!("{:#018x}", rng::rand_u64());
+    }
+
+    let mut bytes = [0; RANDOM_BYTE_COUNT];
+    rng::fill_bytes(&mut bytes);
+    print!("{}", HexDump(&bytes));
+}
+
+/// Rust requires a function with the "panic_handler" attribute [1] to be defined. This is usually
+/// called if a panic occurs, except that this is overridden by the `panic = "abort"` lines in
+/// Cargo.toml in this project to keep things simple. The function name is arbirary as only the
+/// attribute is used to identify which function should be called.
+///
+/// This function prints a message indicating that the kernel has panicked and the debug output
+/// of the `PanicInfo` object passed, which includes the panic message and the line of code where
+/// the panic occurred. The message is sent to both QEMU's debugging console and COM1.
+///
+/// [1]: https://doc.rust-lang.org/reference/runtime.html#the-panic_handler-attribute
+#[panic_handler]
+fn panic(panic_info: &PanicInfo) -> ! {
+    // The panic may have occurred while the console was locked, e.g., if a deadlock involving it
+    // was detected. The code holding the lock will never run again, so it is safe to bypass it.
+    unsafe {
+        qemu_console::emergency_write(format_args!("\nKERNEL PANIC\n{panic_info:#?}\n"));
+    }
+
+    // Also send the message to COM1 with other errors, unless the panic occurred while it was
+    // locked.
+    if let Some(mut serial) = serial::SERIAL.try_lock() {
+        writeln!(serial, "KERNEL PANIC\n{panic_info:#?}").unwrap();
+    }
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
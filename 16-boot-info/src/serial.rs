@@ -0,0 +1,84 @@
+//! Sends data to the first serial port, COM1, which is a 16550-compatible UART.
+//!
+//! Only output is supported. The port is configured for 38400 baud, 8 data bits, no parity and 1
+//! stop bit, although QEMU ignores these settings when it passes the data to the host.
+
+use crate::arch::portio::{Port, PortBlock};
+use crate::sync::Mutex;
+use core::fmt::{self, Write};
+use x86_64::instructions::port::{ReadOnlyAccess, WriteOnlyAccess};
+
+// The UART's ports, and the offset of each register within them. The divisor latch registers
+// replace the data and interrupt enable registers while the line control register's DLAB bit is
+// set.
+const COM1_PORTS: PortBlock = unsafe { PortBlock::new(0x3F8, 8) };
+const DATA_OFFSET: u16 = 0;
+const INTERRUPT_ENABLE_OFFSET: u16 = 1;
+const DIVISOR_LOW_OFFSET: u16 = 0;
+const DIVISOR_HIGH_OFFSET: u16 = 1;
+const FIFO_CONTROL_OFFSET: u16 = 2;
+const LINE_CONTROL_OFFSET: u16 = 3;
+const MODEM_CONTROL_OFFSET: u16 = 4;
+const LINE_STATUS_OFFSET: u16 = 5;
+
+const LINE_CONTROL_DLAB: u8 = 0x80;
+const LINE_CONTROL_8N1: u8 = 0x03;
+const FIFO_CONTROL_ENABLE_AND_CLEAR: u8 = 0xC7;
+const MODEM_CONTROL_DTR_RTS: u8 = 0x03;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 0x20;
+
+/// The divisor of the UART's 115200 Hz clock that gives 38400 baud.
+const BAUD_DIVISOR: u16 = 3;
+
+/// A single instance of COM1, protected against multiple accesses by a spinlock-based `Mutex`.
+pub static SERIAL: Mutex<Serial> = Mutex::new(Serial::new());
+
+pub struct Serial {
+    data: Port<u8, WriteOnlyAccess>,
+    line_status: Port<u8, ReadOnlyAccess>,
+}
+
+impl Serial {
+    const fn new() -> Self {
+        Self {
+            data: COM1_PORTS.port(DATA_OFFSET),
+            line_status: COM1_PORTS.port(LINE_STATUS_OFFSET),
+        }
+    }
+
+    /// Configures the UART's speed and data format, and disables its interrupts. Output written
+    /// before this is called may be lost on real hardware, but not in QEMU.
+    pub fn init(&mut self) {
+        let port = |offset, value| COM1_PORTS.port::<u8, WriteOnlyAccess>(offset).write(value);
+        let [divisor_low, divisor_high] = BAUD_DIVISOR.to_le_bytes();
+
+        port(INTERRUPT_ENABLE_OFFSET, 0);
+        port(LINE_CONTROL_OFFSET, LINE_CONTROL_DLAB);
+        port(DIVISOR_LOW_OFFSET, divisor_low);
+        port(DIVISOR_HIGH_OFFSET, divisor_high);
+        port(LINE_CONTROL_OFFSET, LINE_CONTROL_8N1);
+        port(FIFO_CONTROL_OFFSET, FIFO_CONTROL_ENABLE_AND_CLEAR);
+        port(MODEM_CONTROL_OFFSET, MODEM_CONTROL_DTR_RTS);
+    }
+
+    /// Waits until the UART can accept another byte, then sends `b`.
+    fn write_byte(&mut self, b: u8) {
+        while self.line_status.read() & LINE_STATUS_TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+
+        self.data.write(b);
+    }
+}
+
+impl Write for Serial {
+    /// Sends the given string to COM1. This function is always successful so never returns an
+    /// error.
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        for b in s.bytes() {
+            self.write_byte(b);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+//! Records timestamped events in a fixed-size in-memory ring buffer.
+//!
+//! Sending a message to QEMU's debugging console takes an I/O port access, and therefore an exit
+//! to QEMU, for every byte, which is too slow to do for every event of interest. Events are instead
+//! recorded with the `trace_event!` macro, which formats the message into the buffer, and the
+//! buffer is only output when `dump()` is called. Once the buffer is full, each new event
+//! overwrites the oldest one. Each event belongs to a `Category`, and recording can be enabled or
+//! disabled for each category at runtime. Events in disabled categories are not even formatted.
+
+use crate::println;
+use crate::sync::Mutex;
+use core::arch::x86_64::_rdtsc;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// The number of events the buffer holds.
+const CAPACITY: usize = 128;
+
+/// The maximum length of an event's message in bytes. Longer messages are truncated.
+const MESSAGE_LEN: usize = 80;
+
+/// The buffer of events, protected against multiple accesses by a spinlock-based `Mutex`.
+static TRACE_BUFFER: Mutex<TraceBuffer> = Mutex::new(TraceBuffer::new());
+
+/// A bitmap of the categories whose events are recorded, with bit `n` representing the category
+/// whose value is `n`. All categories are enabled initially.
+static ENABLED_CATEGORIES: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// The part of the kernel an event relates to.
+#[derive(Clone, Copy, Debug)]
+pub enum Category {
+    Boot,
+    FwCfg,
+    Rng,
+}
+
+impl Category {
+    fn mask(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Records an event in the trace buffer if its category is enabled. The arguments following the
+/// category are the same as those of `format_args!`, e.g.,
+/// `trace_event!(Category::FwCfg, "Read {} bytes", len)`.
+#[macro_export]
+macro_rules! trace_event {
+    ($category:expr, $($arg:tt)*) => {{
+        let category = $category;
+        if $crate::trace::is_enabled(category) {
+            $crate::trace::_record(category, format_args!($($arg)*));
+        }
+    }};
+}
+
+/// Enables or disables the recording of events in `category`.
+#[allow(dead_code)] // Categories are all enabled unless a developer chooses otherwise
+pub fn set_enabled(category: Category, enabled: bool) {
+    if enabled {
+        ENABLED_CATEGORIES.fetch_or(category.mask(), Ordering::Relaxed);
+    } else {
+        ENABLED_CATEGORIES.fetch_and(!category.mask(), Ordering::Relaxed);
+    }
+}
+
+/// Returns `true` if events in `category` are recorded.
+pub fn is_enabled(category: Category) -> bool {
+    ENABLED_CATEGORIES.load(Ordering::Relaxed) & category.mask() != 0
+}
+
+/// Records an event in the trace buffer, regardless of whether its category is enabled.
+///
+/// This function is intended only for internal use, but is declared `pub` to allow its use from
+/// macros.
+#[doc(hidden)]
+pub fn _record(category: Category, args: fmt::Arguments) {
+    let timestamp = unsafe { _rdtsc() };
+    TRACE_BUFFER.lock().record(timestamp, category, args);
+}
+
+/// Outputs the events in the trace buffer to QEMU's debugging console, oldest first. Timestamps
+/// are time stamp counter values relative to the oldest event.
+pub fn dump() {
+    let trace_buffer = TRACE_BUFFER.lock();
+
+    println!(
+        "Trace buffer ({} events, {} overwritten):",
+        trace_buffer.len, trace_buffer.overwritten
+    );
+
+    let start = trace_buffer
+        .events()
+        .next()
+        .map_or(0, |event| event.timestamp);
+    for event in trace_buffer.events() {
+        println!(
+            "{:>12} {:<6} {}",
+            event.timestamp - start,
+            event.category_name(),
+            event.message()
+        );
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Event {
+    timestamp: u64,
+    category: Category,
+    message: [u8; MESSAGE_LEN],
+    message_len: usize,
+}
+
+impl Event {
+    const EMPTY: Event = Event {
+        timestamp: 0,
+        category: Category::Boot,
+        message: [0; MESSAGE_LEN],
+        message_len: 0,
+    };
+
+    fn category_name(&self) -> &'static str {
+        match self.category {
+            Category::Boot => "boot",
+            Category::FwCfg => "fw_cfg",
+            Category::Rng => "rng",
+        }
+    }
+
+    /// Returns the event's message. This is always valid UTF-8 because messages are only
+    /// truncated at character boundaries.
+    fn message(&self) -> &str {
+        core::str::from_utf8(&self.message[..self.message_len]).unwrap_or("?")
+    }
+}
+
+impl Write for Event {
+    /// Appends `s` to the event's message, truncating it at a character boundary if the message
+    /// is full. This is always successful so never returns an error.
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        let mut len = s.len().min(MESSAGE_LEN - self.message_len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.message[self.message_len..self.message_len + len]
+            .copy_from_slice(&s.as_bytes()[..len]);
+        self.message_len += len;
+        Ok(())
+    }
+}
+
+struct TraceBuffer {
+    events: [Event; CAPACITY],
+    next: usize,
+    len: usize,
+    overwritten: u64,
+}
+
+impl TraceBuffer {
+    const fn new() -> Self {
+        Self {
+            events: [Event::EMPTY; CAPACITY],
+            next: 0,
+            len: 0,
+            overwritten: 0,
+        }
+    }
+
+    fn record(&mut self, timestamp: u64, category: Category, args: fmt::Arguments) {
+        let event = &mut self.events[self.next];
+        event.timestamp = timestamp;
+        event.category = category;
+        event.message_len = 0;
+        event.write_fmt(args).unwrap();
+
+        self.next = (self.next + 1) % CAPACITY;
+        if self.len == CAPACITY {
+            self.overwritten += 1;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    /// Returns an iterator over the events in the buffer, oldest first.
+    fn events(&self) -> impl Iterator<Item = &Event> {
+        let oldest = (self.next + CAPACITY - self.len) % CAPACITY;
+        (0..self.len).map(move |i| &self.events[(oldest + i) % CAPACITY])
+    }
+}
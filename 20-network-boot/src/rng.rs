@@ -0,0 +1,262 @@
+//! Generates random numbers for use by the rest of the kernel.
+//!
+//! If the CPU supports the RDRAND instruction, random numbers are read directly from its hardware
+//! generator. Otherwise, or if RDRAND repeatedly fails, they are generated by a ChaCha20-based
+//! software generator. This is seeded from RDSEED or RDRAND if available, or failing that, from
+//! the jitter in the time taken to read the PIT's counter, as measured by the CPU's time stamp
+//! counter. The jitter-based seed is far weaker than a hardware one, but is the best available
+//! until the kernel has interrupts and devices to gather entropy from.
+
+use crate::trace::Category;
+use crate::trace_event;
+use core::arch::x86_64::{__cpuid, __cpuid_count, _rdseed64_step, _rdtsc};
+use simpleos_kernel::arch::portio::{Port, PortBlock};
+use simpleos_kernel::sync::Mutex;
+use x86_64::instructions::port::{ReadOnlyAccess, WriteOnlyAccess};
+use x86_64::instructions::random::RdRand;
+
+// CPUID leaves and the feature bit reporting support for the RDSEED instruction.
+const CPUID_MAX_LEAF_LEAF: u32 = 0;
+const CPUID_EXTENDED_FEATURES_LEAF: u32 = 7;
+const CPUID_EXTENDED_FEATURES_EBX_RDSEED: u32 = 1 << 18;
+
+// Intel recommends retrying RDRAND up to 10 times before assuming the hardware has failed.
+const RDRAND_RETRIES: usize = 10;
+const RDSEED_RETRIES: usize = 100;
+
+// The PIT's ports, and the offsets of those used to read the current value of the counter of
+// channel 0.
+const PIT_PORTS: PortBlock = unsafe { PortBlock::new(0x40, 4) };
+const PIT_CHANNEL_0_OFFSET: u16 = 0;
+const PIT_COMMAND_OFFSET: u16 = 3;
+const PIT_LATCH_CHANNEL_0: u8 = 0x00;
+
+// The number of timing measurements mixed into each 64-bit word of a jitter-based seed.
+const JITTER_SAMPLES_PER_WORD: usize = 64;
+
+/// The "expand 32-byte k" constant that starts every ChaCha20 block.
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+const CHACHA_KEY_WORDS: usize = 8;
+const CHACHA_BLOCK_WORDS: usize = 16;
+const CHACHA_DOUBLE_ROUNDS: usize = 10;
+
+/// A single instance of the kernel's random number generator, protected against multiple accesses
+/// by a spinlock-based `Mutex`. This is `None` until `init()` is called.
+static RNG: Mutex<Option<Rng>> = Mutex::new(None);
+
+/// The source of the entropy used to seed the software generator.
+#[derive(Clone, Copy, Debug)]
+pub enum SeedSource {
+    RdSeed,
+    RdRand,
+    TimingJitter,
+}
+
+struct Rng {
+    rdrand: Option<RdRand>,
+    chacha: ChaCha20,
+}
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.rdrand
+            .and_then(rdrand_u64)
+            .unwrap_or_else(|| self.chacha.next_u64())
+    }
+}
+
+/// Detects the hardware random number support available and seeds the software generator.
+/// Returns `true` if RDRAND is used to generate random numbers, and the source used to seed the
+/// software generator. This must be called before any other function in this module.
+pub fn init() -> (bool, SeedSource) {
+    let rdrand = RdRand::new();
+    let (seed, seed_source) = seed(rdrand);
+    trace_event!(
+        Category::Rng,
+        "Initialized: RDRAND {}, seeded from {seed_source:?}",
+        rdrand.is_some()
+    );
+
+    *RNG.lock() = Some(Rng {
+        rdrand,
+        chacha: ChaCha20::new(seed),
+    });
+
+    (rdrand.is_some(), seed_source)
+}
+
+/// Returns a random `u64`.
+///
+/// Panics if `init()` has not been called.
+pub fn rand_u64() -> u64 {
+    RNG.lock()
+        .as_mut()
+        .expect("rng::init() has not been called")
+        .next_u64()
+}
+
+/// Fills `buf` with random bytes.
+///
+/// Panics if `init()` has not been called.
+pub fn fill_bytes(buf: &mut [u8]) {
+    let mut rng = RNG.lock();
+    let rng = rng.as_mut().expect("rng::init() has not been called");
+
+    for chunk in buf.chunks_mut(8) {
+        let bytes = rng.next_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+/// Returns a seed for the software generator from the best source available.
+fn seed(rdrand: Option<RdRand>) -> ([u32; CHACHA_KEY_WORDS], SeedSource) {
+    if rdseed_supported() {
+        if let Some(seed) = seed_from(rdseed_u64) {
+            return (seed, SeedSource::RdSeed);
+        }
+    }
+
+    if let Some(seed) = rdrand.and_then(|rdrand| seed_from(|| rdrand_u64(rdrand))) {
+        return (seed, SeedSource::RdRand);
+    }
+
+    let seed = seed_from(|| Some(jitter_u64())).expect("timing jitter always produces a value");
+    (seed, SeedSource::TimingJitter)
+}
+
+/// Builds a seed from four 64-bit values returned by `next`, or returns `None` if `next` fails.
+fn seed_from<F: FnMut() -> Option<u64>>(mut next: F) -> Option<[u32; CHACHA_KEY_WORDS]> {
+    let mut seed = [0; CHACHA_KEY_WORDS];
+    for pair in seed.chunks_mut(2) {
+        let value = next()?;
+        pair[0] = value as u32;
+        pair[1] = (value >> 32) as u32;
+    }
+    Some(seed)
+}
+
+/// Returns `true` if the CPU supports the RDSEED instruction. The extended features leaf must be
+/// checked to exist before it is read, as older CPUs return data from a different leaf instead.
+fn rdseed_supported() -> bool {
+    __cpuid(CPUID_MAX_LEAF_LEAF).eax >= CPUID_EXTENDED_FEATURES_LEAF
+        && __cpuid_count(CPUID_EXTENDED_FEATURES_LEAF, 0).ebx & CPUID_EXTENDED_FEATURES_EBX_RDSEED
+            != 0
+}
+
+/// Reads a value from RDRAND, retrying if the hardware is temporarily unable to provide one.
+fn rdrand_u64(rdrand: RdRand) -> Option<u64> {
+    (0..RDRAND_RETRIES).find_map(|_| rdrand.get_u64())
+}
+
+/// Reads a value from RDSEED, retrying if the hardware is temporarily unable to provide one.
+/// RDSEED fails more often than RDRAND as it waits for fresh entropy, so more retries are allowed.
+/// This must only be called if `rdseed_supported()` returns `true`.
+fn rdseed_u64() -> Option<u64> {
+    (0..RDSEED_RETRIES).find_map(|_| {
+        let mut value = 0;
+        match unsafe { _rdseed64_step(&mut value) } {
+            1 => Some(value),
+            _ => None,
+        }
+    })
+}
+
+/// Returns a value derived from the variation in the time taken to read the PIT's counter. Each
+/// read is an I/O port access, which takes a variable number of cycles, particularly in a virtual
+/// machine where it causes an exit to the hypervisor. Both the time taken and the counter value
+/// read are mixed into the result.
+fn jitter_u64() -> u64 {
+    let mut command: Port<u8, WriteOnlyAccess> = PIT_PORTS.port(PIT_COMMAND_OFFSET);
+    let mut channel_0: Port<u8, ReadOnlyAccess> = PIT_PORTS.port(PIT_CHANNEL_0_OFFSET);
+    let mut value: u64 = 0;
+
+    for _ in 0..JITTER_SAMPLES_PER_WORD {
+        let start = unsafe { _rdtsc() };
+        command.write(PIT_LATCH_CHANNEL_0);
+        let counter = u16::from_le_bytes([channel_0.read(), channel_0.read()]);
+        let elapsed = unsafe { _rdtsc() }.wrapping_sub(start);
+
+        value = value.rotate_left(7) ^ elapsed ^ (u64::from(counter) << 32);
+    }
+
+    value
+}
+
+/// A random number generator based on the ChaCha20 stream cipher. This uses the original variant
+/// of ChaCha20 with a 64-bit block counter rather than the one in RFC 8439, as a nonce is not
+/// needed. The seed is used as the key, the nonce is zero, and the counter is incremented for each
+/// block of output.
+struct ChaCha20 {
+    key: [u32; CHACHA_KEY_WORDS],
+    counter: u64,
+    block: [u32; CHACHA_BLOCK_WORDS],
+    index: usize,
+}
+
+impl ChaCha20 {
+    fn new(key: [u32; CHACHA_KEY_WORDS]) -> Self {
+        Self {
+            key,
+            counter: 0,
+            block: [0; CHACHA_BLOCK_WORDS],
+            index: CHACHA_BLOCK_WORDS,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        u64::from(self.next_u32()) | (u64::from(self.next_u32()) << 32)
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.index == CHACHA_BLOCK_WORDS {
+            self.generate_block();
+        }
+
+        let value = self.block[self.index];
+        self.index += 1;
+        value
+    }
+
+    /// Generates the next block of output from the key and block counter.
+    fn generate_block(&mut self) {
+        let mut state = [0; CHACHA_BLOCK_WORDS];
+        state[..4].copy_from_slice(&CHACHA_CONSTANTS);
+        state[4..12].copy_from_slice(&self.key);
+        state[12] = self.counter as u32;
+        state[13] = (self.counter >> 32) as u32;
+
+        let mut working = state;
+        for _ in 0..CHACHA_DOUBLE_ROUNDS {
+            // Column round
+            quarter_round(&mut working, 0, 4, 8, 12);
+            quarter_round(&mut working, 1, 5, 9, 13);
+            quarter_round(&mut working, 2, 6, 10, 14);
+            quarter_round(&mut working, 3, 7, 11, 15);
+
+            // Diagonal round
+            quarter_round(&mut working, 0, 5, 10, 15);
+            quarter_round(&mut working, 1, 6, 11, 12);
+            quarter_round(&mut working, 2, 7, 8, 13);
+            quarter_round(&mut working, 3, 4, 9, 14);
+        }
+
+        for (out, (w, s)) in self.block.iter_mut().zip(working.iter().zip(state.iter())) {
+            *out = w.wrapping_add(*s);
+        }
+
+        self.counter = self.counter.wrapping_add(1);
+        self.index = 0;
+    }
+}
+
+/// The ChaCha quarter round, applied to four words of the state.
+fn quarter_round(state: &mut [u32; CHACHA_BLOCK_WORDS], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
@@ -0,0 +1,675 @@
+/// Adds UEFI information to a kernel file to make it bootable via UEFI.
+///
+/// The kernel source needs to be compiled before it can be made bootable and this must be done
+/// using Cargo's binary artifact dependency functionality so that its location is set in an
+/// environment variable before this file is built. The UEFI-enabled kernel is saved in the same
+/// directory as the kernel object and has the same name with "_uefi" appended.
+///
+/// The disk image also contains a boot configuration which the bootloader reads before loading
+/// the kernel. This is used to request a minimum framebuffer resolution.
+///
+/// Files on the host can be made available to the kernel by passing one or more `--fw-cfg FILE`
+/// options, e.g., `cargo run -p add_uefi_boot -- --fw-cfg test.txt`. Each is passed to QEMU's
+/// fw_cfg interface with a name consisting of "opt/simpleos/" followed by the file's name.
+///
+/// The kernel reports the time taken by each stage of boot. Passing `--boot-report FILE` saves
+/// these lines of the kernel's output to FILE, so they can be compared with those of other runs.
+///
+/// The kernel's output is sent to this program's stdout by default. Passing `--debugcon FILE`
+/// saves it to FILE instead. The kernel also sends its error messages to the serial port COM1,
+/// and passing `--serial FILE` saves these to FILE, which separates them from the rest of the
+/// output, e.g., for checking in automated tests.
+///
+/// Passing `--bios` creates a disk image that boots via BIOS rather than UEFI, which is saved with
+/// "_bios" appended to the kernel's name instead. Passing `--no-run` creates the disk image and
+/// outputs its path without running QEMU.
+///
+/// Files for the kernel to read from disk can be added to the disk image's boot partition by
+/// passing one or more `--boot-file FILE` options. Each is saved in the partition's "simpleos"
+/// directory. Alternatively, passing `--data-disk DIR` creates a second disk image containing a
+/// FAT file system with a copy of DIR's contents, which is attached to QEMU as a second disk. It is
+/// saved with "_data" appended to the kernel's name.
+///
+/// Passing `--pxe DIR` saves the files needed to boot the kernel over a network via UEFI in DIR,
+/// instead of creating a boot disk, and outputs instructions for serving them to QEMU or to other
+/// machines on the network. QEMU is then run, booting the kernel from DIR over its emulated
+/// network, unless `--no-run` is also passed.
+///
+/// If the host supports KVM, QEMU uses it to run the kernel directly on the host's CPU, which
+/// emulates a CPU with the same features as the host's. Passing `--no-kvm` makes QEMU emulate its
+/// default CPU instead. The virtual machine can be changed with `--machine TYPE`, where TYPE is
+/// "pc" or "q35", `--smp CPUS`, which sets the number of CPUs, and `--memory SIZE`, which sets the
+/// amount of RAM, e.g., "512M" or "2G".
+///
+/// Passing `--qmp SOCKET` makes QEMU listen for QEMU Machine Protocol (QMP) connections on the Unix
+/// socket SOCKET, through which other programs can control the virtual machine while it runs,
+/// e.g., with `cargo xtask qmp SOCKET reset`.
+///
+/// Passing `--overlay FILE` boots from FILE, a qcow2 disk image that stores changes to the disk
+/// image on top of it, instead of the disk image itself. FILE is created by `qemu-img` if it
+/// doesn't exist. Snapshots of the running virtual machine can be saved in FILE, e.g., with
+/// `cargo xtask qmp SOCKET savevm NAME`, and passing `--loadvm NAME` as well restores the snapshot
+/// NAME at startup instead of booting. FILE must be deleted after the kernel is changed, because a
+/// snapshot contains the kernel that was running when it was saved, so the runner exits if FILE is
+/// older than the kernel.
+use bootloader::{BootConfig, DiskImageBuilder};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{self, Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+const UEFI_EXTENSION: &str = "_uefi";
+const BIOS_EXTENSION: &str = "_bios";
+const DATA_DISK_EXTENSION: &str = "_data";
+const UEFI_FIRMWARE_PATH: &str = "/usr/share/ovmf/OVMF.fd"; // Set to location of OVMF firmware
+
+// Minimum framebuffer resolution requested from the bootloader. These must match the constants of
+// the same names in the kernel's src/boot_config.rs, which checks the resolution provided.
+const MINIMUM_FRAMEBUFFER_WIDTH: u64 = 1024;
+const MINIMUM_FRAMEBUFFER_HEIGHT: u64 = 768;
+
+// The option used to pass host files to the kernel, and the prefix and maximum length of the fw_cfg
+// names given to them. The prefix must match `HOST_FILE_PREFIX` in the kernel's src/fw_cfg.rs.
+// QEMU reserves names that don't start with "opt/" for its own use.
+const FW_CFG_OPTION: &str = "--fw-cfg";
+const FW_CFG_FILE_PREFIX: &str = "opt/simpleos/";
+const FW_CFG_MAX_NAME_LEN: usize = 55;
+
+// The option used to save the kernel's boot time report to a file, and the text at the start of
+// each line of the report. The prefix must match `REPORT_PREFIX` in the kernel's
+// src/boot_timing.rs.
+const BOOT_REPORT_OPTION: &str = "--boot-report";
+const BOOT_REPORT_PREFIX: &str = "boot-time:";
+
+// The options used to save the output of QEMU's debugging console and of the serial port COM1 to
+// files.
+const DEBUGCON_OPTION: &str = "--debugcon";
+const SERIAL_OPTION: &str = "--serial";
+
+// The options used to boot via BIOS rather than UEFI, and to create the disk image without running
+// it.
+const BIOS_OPTION: &str = "--bios";
+const NO_RUN_OPTION: &str = "--no-run";
+
+// The options used to add files to the boot partition, and to create a data disk from a directory,
+// and the directory in the boot partition that files are added to.
+const BOOT_FILE_OPTION: &str = "--boot-file";
+const DATA_DISK_OPTION: &str = "--data-disk";
+const BOOT_FILE_DIR: &str = "simpleos";
+
+// The FAT volume label of the data disk, and the number of bytes its size is rounded up to. The
+// size also includes an extra unit of this size, to leave room for the file system's own
+// structures.
+const DATA_DISK_LABEL: [u8; 11] = *b"SIMPLEOS   ";
+const DATA_DISK_SIZE_UNIT: u64 = 1024 * 1024;
+
+// The option used to create the files needed to boot over a network, the name the `bootloader`
+// crate gives the bootloader in the directory it creates, and the QEMU network device and its ID.
+// OVMF can only boot over a network device that QEMU provides a UEFI driver for, which includes
+// virtio-net.
+const PXE_OPTION: &str = "--pxe";
+const PXE_BOOT_FILE: &str = "bootloader";
+const PXE_NETWORK_DEVICE: &str = "virtio-net-pci";
+const PXE_NETDEV_ID: &str = "net0";
+
+// The options used to disable KVM, and to set the machine type, the number of CPUs and the amount
+// of RAM of the virtual machine, and the machine types that can be set. The "pc" machine emulates a
+// chipset from 1996, while "q35" emulates one from 2007 with PCI Express.
+const NO_KVM_OPTION: &str = "--no-kvm";
+const MACHINE_OPTION: &str = "--machine";
+const SMP_OPTION: &str = "--smp";
+const MEMORY_OPTION: &str = "--memory";
+const MACHINE_TYPES: [&str; 2] = ["pc", "q35"];
+
+/// The device through which QEMU uses KVM.
+const KVM_DEVICE_PATH: &str = "/dev/kvm";
+
+/// The option used to make QEMU listen for QMP connections.
+const QMP_OPTION: &str = "--qmp";
+
+// The options used to boot from a qcow2 overlay of the disk image, in which snapshots can be saved,
+// and to restore a snapshot at startup.
+const OVERLAY_OPTION: &str = "--overlay";
+const LOADVM_OPTION: &str = "--loadvm";
+
+/// The options passed on the command line.
+struct Args {
+    fw_cfg_files: Vec<PathBuf>,
+    boot_report: Option<PathBuf>,
+    debugcon: Option<PathBuf>,
+    serial: Option<PathBuf>,
+    bios: bool,
+    no_run: bool,
+    boot_files: Vec<PathBuf>,
+    data_disk: Option<PathBuf>,
+    pxe: Option<PathBuf>,
+    no_kvm: bool,
+    machine: Option<String>,
+    smp: Option<u32>,
+    memory: Option<String>,
+    qmp: Option<PathBuf>,
+    overlay: Option<PathBuf>,
+    loadvm: Option<String>,
+}
+
+fn main() {
+    let args = parse_args();
+
+    let bootable_kernel_path = match &args.pxe {
+        Some(pxe_dir) => {
+            create_pxe_dir(pxe_dir, &args.boot_files);
+            print_pxe_instructions(pxe_dir);
+            None
+        }
+        None => Some(create_disk_image(args.bios, &args.boot_files)),
+    };
+    let data_disk_path = args.data_disk.as_deref().map(create_data_disk);
+
+    if args.no_run {
+        for path in bootable_kernel_path.iter().chain(&data_disk_path) {
+            println!("{}", path.display());
+        }
+        return;
+    }
+
+    let mut cmd = Command::new("qemu-system-x86_64");
+    if !args.bios {
+        cmd.arg("-bios").arg(UEFI_FIRMWARE_PATH);
+    }
+    if !args.no_kvm && kvm_available() {
+        cmd.arg("-enable-kvm").arg("-cpu").arg("host");
+    }
+    if let Some(machine) = &args.machine {
+        cmd.arg("-machine").arg(machine);
+    }
+    if let Some(smp) = args.smp {
+        cmd.arg("-smp").arg(smp.to_string());
+    }
+    if let Some(memory) = &args.memory {
+        cmd.arg("-m").arg(memory);
+    }
+    if let Some(qmp) = &args.qmp {
+        // QEMU doesn't wait for a connection before starting the virtual machine.
+        cmd.arg("-qmp")
+            .arg(format!("unix:{},server=on,wait=off", qmp.display()));
+        eprintln!("QMP socket: {}", qmp.display());
+    }
+    if let Some(bootable_kernel_path) = &bootable_kernel_path {
+        let drive = match &args.overlay {
+            Some(overlay) => {
+                create_overlay(overlay, bootable_kernel_path);
+                format!("file={},format=qcow2,index=0,media=disk", overlay.display())
+            }
+            None => format!(
+                "file={},format=raw,index=0,media=disk",
+                bootable_kernel_path.display()
+            ),
+        };
+        cmd.arg("-drive").arg(drive);
+    }
+    if let Some(snapshot) = &args.loadvm {
+        cmd.arg("-loadvm").arg(snapshot);
+    }
+    if let Some(pxe_dir) = &args.pxe {
+        cmd.arg("-netdev").arg(pxe_netdev_arg(pxe_dir));
+        cmd.arg("-device")
+            .arg(format!("{PXE_NETWORK_DEVICE},netdev={PXE_NETDEV_ID}"));
+    }
+    if let Some(data_disk_path) = &data_disk_path {
+        cmd.arg("-drive").arg(format!(
+            "file={},format=raw,index=1,media=disk",
+            data_disk_path.display()
+        ));
+    }
+    let debugcon = add_chardev(&mut cmd, "debugcon", args.debugcon.as_deref());
+    cmd.arg("-debugcon").arg(debugcon); // Pass data sent to QEMU debugging console to stdio or a file
+
+    if let Some(serial) = &args.serial {
+        let serial = add_chardev(&mut cmd, "serial", Some(serial));
+        cmd.arg("-serial").arg(serial);
+    }
+
+    for file in &args.fw_cfg_files {
+        cmd.arg("-fw_cfg").arg(fw_cfg_arg(file));
+    }
+
+    // The report is created before QEMU is started, so that QEMU isn't left running if the report
+    // can't be created.
+    let report = args.boot_report.as_ref().map(|report_path| {
+        cmd.stdout(Stdio::piped());
+        File::create(report_path).unwrap_or_else(|e| {
+            eprintln!("Failed to create '{}': {e}", report_path.display());
+            process::exit(1);
+        })
+    });
+
+    let mut child = cmd
+        .spawn()
+        .expect("Failed to run 'qemu' on the bootable kernel image");
+
+    if let Some(report) = report {
+        let stdout = child.stdout.take().expect("qemu's stdout was not captured");
+        save_boot_report(stdout, report);
+    }
+
+    child
+        .wait()
+        .expect("qemu terminated with an exit status indicating a failure");
+}
+
+/// Creates a disk image containing the kernel that boots via BIOS if `bios` is `true`, or UEFI
+/// otherwise. Each of `boot_files` is added to the image's boot partition. Returns the path of the
+/// image, which is in the same directory as the kernel.
+fn create_disk_image(bios: bool, boot_files: &[PathBuf]) -> PathBuf {
+    let kernel_path_env: &'static str = env!("CARGO_BIN_FILE_KERNEL_kernel");
+    let builder = disk_image_builder(boot_files);
+
+    if bios {
+        let bios_kernel_path = PathBuf::from([kernel_path_env, BIOS_EXTENSION].concat());
+        builder
+            .create_bios_image(&bios_kernel_path)
+            .expect("Failed to create a BIOS-bootable version of your kernel image");
+        bios_kernel_path
+    } else {
+        let uefi_kernel_path = PathBuf::from([kernel_path_env, UEFI_EXTENSION].concat());
+        builder
+            .create_uefi_image(&uefi_kernel_path)
+            .expect("Failed to create a UEFI-enabled version of your kernel image");
+        uefi_kernel_path
+    }
+}
+
+/// Creates a qcow2 disk image at `overlay` that reads any data it doesn't contain from the raw disk
+/// image at `disk_image`, unless it already exists. Exits if `qemu-img` fails, or if an existing
+/// overlay is older than the kernel.
+fn create_overlay(overlay: &Path, disk_image: &Path) {
+    if overlay.exists() {
+        // The disk image is recreated on every run, so an overlay created for an older kernel would
+        // combine its changes and snapshots with a different disk, corrupting both.
+        let modified = |path: &Path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .expect("Failed to read a file's modification time")
+        };
+        if modified(overlay) < modified(Path::new(env!("CARGO_BIN_FILE_KERNEL_kernel"))) {
+            eprintln!(
+                "The overlay '{}' is older than the kernel. Delete it to create a new one.",
+                overlay.display()
+            );
+            process::exit(1);
+        }
+        return;
+    }
+
+    // The path of the backing disk image is stored in the overlay, so it must be absolute.
+    let status = Command::new("qemu-img")
+        .args(["create", "-f", "qcow2", "-F", "raw", "-b"])
+        .arg(absolute_path(disk_image.to_path_buf()))
+        .arg(overlay)
+        .status()
+        .expect("Failed to run 'qemu-img'");
+
+    if !status.success() {
+        eprintln!("Failed to create the overlay '{}'", overlay.display());
+        process::exit(1);
+    }
+}
+
+/// Returns `true` if QEMU can use KVM, i.e., the host's CPU and kernel support it, and this user
+/// can access the KVM device.
+fn kvm_available() -> bool {
+    File::options()
+        .read(true)
+        .write(true)
+        .open(KVM_DEVICE_PATH)
+        .is_ok()
+}
+
+/// Saves the files needed to boot the kernel over a network via UEFI in directory `dir`. These are
+/// the bootloader, the kernel, the bootloader's configuration and each of `boot_files`.
+fn create_pxe_dir(dir: &Path, boot_files: &[PathBuf]) {
+    let builder = disk_image_builder(boot_files);
+
+    // The bootloader crate doesn't create the directories that files are saved in.
+    if !boot_files.is_empty() {
+        fs::create_dir_all(dir.join(BOOT_FILE_DIR))
+            .expect("Failed to create the directory for the boot files");
+    }
+
+    builder
+        .create_uefi_tftp_folder(dir)
+        .expect("Failed to create the files needed to boot your kernel over a network");
+}
+
+/// Outputs instructions for booting the kernel over a network from the files in directory `dir`,
+/// either in QEMU, or on other machines using dnsmasq as a DHCP and TFTP server.
+fn print_pxe_instructions(dir: &Path) {
+    println!(
+        "The files needed to boot over a network are in '{}'.",
+        dir.display()
+    );
+    println!("To boot them in QEMU, run:");
+    println!(
+        "  qemu-system-x86_64 -bios {UEFI_FIRMWARE_PATH} -netdev {} -device {PXE_NETWORK_DEVICE},netdev={PXE_NETDEV_ID} -debugcon stdio",
+        pxe_netdev_arg(dir)
+    );
+    println!("To boot them on machines on the network attached to INTERFACE, which must not have");
+    println!("another DHCP server, run:");
+    println!(
+        "  dnsmasq --no-daemon --port=0 --interface=INTERFACE --dhcp-range=FIRST_IP,LAST_IP --enable-tftp --tftp-root={} --dhcp-boot={PXE_BOOT_FILE}",
+        dir.display()
+    );
+    println!("The machines must be set to boot over the network via UEFI.");
+}
+
+/// Returns the value of the QEMU `-netdev` argument that provides a network with a TFTP server
+/// serving the files in directory `dir`, and tells QEMU's firmware to boot from them.
+fn pxe_netdev_arg(dir: &Path) -> String {
+    format!(
+        "user,id={PXE_NETDEV_ID},tftp={},bootfile={PXE_BOOT_FILE}",
+        dir.display()
+    )
+}
+
+/// Returns a `DiskImageBuilder` that adds the kernel, the bootloader's configuration and each of
+/// `boot_files` to the disk image.
+fn disk_image_builder(boot_files: &[PathBuf]) -> DiskImageBuilder {
+    let mut boot_config = BootConfig::default();
+    boot_config.frame_buffer.minimum_framebuffer_width = Some(MINIMUM_FRAMEBUFFER_WIDTH);
+    boot_config.frame_buffer.minimum_framebuffer_height = Some(MINIMUM_FRAMEBUFFER_HEIGHT);
+
+    let mut builder = DiskImageBuilder::new(PathBuf::from(env!("CARGO_BIN_FILE_KERNEL_kernel")));
+    builder.set_boot_config(&boot_config);
+    for file in boot_files {
+        builder.set_file(boot_file_name(file), file.clone());
+    }
+    builder
+}
+
+/// Returns the path in the boot partition that `file` is saved to. Exits if `file` doesn't exist
+/// or has no file name.
+fn boot_file_name(file: &Path) -> String {
+    let file_name = match file.file_name() {
+        Some(file_name) if file.is_file() => file_name,
+        _ => {
+            eprintln!("'{}' is not a file", file.display());
+            process::exit(1);
+        }
+    };
+
+    format!("{BOOT_FILE_DIR}/{}", file_name.to_string_lossy())
+}
+
+/// Creates a disk image containing a FAT file system with a copy of the contents of directory
+/// `dir`. The image is not partitioned, i.e., the file system starts at the first sector. Returns
+/// the path of the image, which is in the same directory as the kernel.
+fn create_data_disk(dir: &Path) -> PathBuf {
+    let data_disk_path =
+        PathBuf::from([env!("CARGO_BIN_FILE_KERNEL_kernel"), DATA_DISK_EXTENSION].concat());
+
+    if let Err(e) = write_data_disk(dir, &data_disk_path) {
+        eprintln!("Failed to create a data disk from '{}': {e}", dir.display());
+        process::exit(1);
+    }
+
+    data_disk_path
+}
+
+/// Writes a disk image containing a FAT file system with a copy of the contents of directory `dir`
+/// to `path`.
+fn write_data_disk(dir: &Path, path: &Path) -> io::Result<()> {
+    let size =
+        dir_size(dir)?.div_ceil(DATA_DISK_SIZE_UNIT) * DATA_DISK_SIZE_UNIT + DATA_DISK_SIZE_UNIT;
+    let disk = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    disk.set_len(size)?;
+
+    let format_options = fatfs::FormatVolumeOptions::new().volume_label(DATA_DISK_LABEL);
+    fatfs::format_volume(&disk, format_options)?;
+    let file_system = fatfs::FileSystem::new(&disk, fatfs::FsOptions::new())?;
+    let root_dir = file_system.root_dir();
+    copy_dir_to_fat(dir, &root_dir)
+}
+
+/// Returns the total size in bytes of the files in directory `dir` and its subdirectories.
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        size += if entry.file_type()?.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            entry.metadata()?.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Copies the files in directory `dir` and its subdirectories into `fat_dir`.
+fn copy_dir_to_fat(dir: &Path, fat_dir: &fatfs::Dir<&File>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        if entry.file_type()?.is_dir() {
+            copy_dir_to_fat(&entry.path(), &fat_dir.create_dir(&name)?)?;
+        } else {
+            let mut fat_file = fat_dir.create_file(&name)?;
+            fat_file.truncate()?;
+            io::copy(&mut File::open(entry.path())?, &mut fat_file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the options passed on the command line. Prints a usage message and exits if an
+/// unrecognized argument is passed.
+fn parse_args() -> Args {
+    let mut parsed = Args {
+        fw_cfg_files: Vec::new(),
+        boot_report: None,
+        debugcon: None,
+        serial: None,
+        bios: false,
+        no_run: false,
+        boot_files: Vec::new(),
+        data_disk: None,
+        pxe: None,
+        no_kvm: false,
+        machine: None,
+        smp: None,
+        memory: None,
+        qmp: None,
+        overlay: None,
+        loadvm: None,
+    };
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            FW_CFG_OPTION => parsed.fw_cfg_files.push(option_value(&mut args)),
+            BOOT_REPORT_OPTION => parsed.boot_report = Some(option_value(&mut args)),
+            DEBUGCON_OPTION => parsed.debugcon = Some(option_value(&mut args)),
+            SERIAL_OPTION => parsed.serial = Some(option_value(&mut args)),
+            BIOS_OPTION => parsed.bios = true,
+            NO_RUN_OPTION => parsed.no_run = true,
+            BOOT_FILE_OPTION => parsed.boot_files.push(option_value(&mut args)),
+            DATA_DISK_OPTION => parsed.data_disk = Some(option_value(&mut args)),
+            PXE_OPTION => parsed.pxe = Some(option_value(&mut args)),
+            NO_KVM_OPTION => parsed.no_kvm = true,
+            MACHINE_OPTION => parsed.machine = Some(option_string(&mut args)),
+            SMP_OPTION => parsed.smp = Some(option_cpus(&mut args)),
+            MEMORY_OPTION => parsed.memory = Some(option_string(&mut args)),
+            QMP_OPTION => parsed.qmp = Some(absolute_path(option_value(&mut args))),
+            OVERLAY_OPTION => parsed.overlay = Some(option_value(&mut args)),
+            LOADVM_OPTION => parsed.loadvm = Some(option_string(&mut args)),
+            _ => usage(),
+        }
+    }
+
+    // The boot time report is extracted from the debugging console's output on stdout.
+    if parsed.boot_report.is_some() && parsed.debugcon.is_some() {
+        eprintln!("{BOOT_REPORT_OPTION} can't be used with {DEBUGCON_OPTION}");
+        process::exit(1);
+    }
+
+    if let Some(machine) = &parsed.machine {
+        if !MACHINE_TYPES.contains(&machine.as_str()) {
+            eprintln!(
+                "{MACHINE_OPTION} must be one of: {}",
+                MACHINE_TYPES.join(", ")
+            );
+            process::exit(1);
+        }
+    }
+
+    // The bootloader crate only supports booting over a network via UEFI.
+    if parsed.pxe.is_some() && parsed.bios {
+        eprintln!("{PXE_OPTION} can't be used with {BIOS_OPTION}");
+        process::exit(1);
+    }
+
+    // Snapshots are saved in the overlay, and QEMU can only save them if every writable disk
+    // supports them, which raw disk images don't.
+    if parsed.loadvm.is_some() && parsed.overlay.is_none() {
+        eprintln!("{LOADVM_OPTION} can only be used with {OVERLAY_OPTION}");
+        process::exit(1);
+    }
+    if parsed.overlay.is_some() && (parsed.pxe.is_some() || parsed.data_disk.is_some()) {
+        eprintln!("{OVERLAY_OPTION} can't be used with {PXE_OPTION} or {DATA_DISK_OPTION}");
+        process::exit(1);
+    }
+
+    parsed
+}
+
+/// Returns the file name that follows an option on the command line. Prints a usage message and
+/// exits if there isn't one.
+fn option_value(args: &mut impl Iterator<Item = String>) -> PathBuf {
+    PathBuf::from(option_string(args))
+}
+
+/// Returns the value that follows an option on the command line. Prints a usage message and exits
+/// if there isn't one.
+fn option_string(args: &mut impl Iterator<Item = String>) -> String {
+    args.next().unwrap_or_else(|| usage())
+}
+
+/// Returns the number of CPUs that follows `--smp` on the command line. Exits with an error
+/// message if it isn't a number, or is zero.
+fn option_cpus(args: &mut impl Iterator<Item = String>) -> u32 {
+    let value = option_string(args);
+    match value.parse::<u32>() {
+        Ok(0) => {
+            eprintln!("{SMP_OPTION} must be greater than zero");
+            process::exit(1);
+        }
+        Ok(cpus) => cpus,
+        Err(_) => {
+            eprintln!("{SMP_OPTION} must be a number of CPUs, not '{value}'");
+            process::exit(1);
+        }
+    }
+}
+
+/// Returns `file` as an absolute path, so that it refers to the same file when it is used by
+/// programs run in other directories. Exits if the current directory can't be determined.
+fn absolute_path(file: PathBuf) -> PathBuf {
+    path::absolute(&file).unwrap_or_else(|e| {
+        eprintln!(
+            "Failed to find the absolute path of '{}': {e}",
+            file.display()
+        );
+        process::exit(1);
+    })
+}
+
+/// Prints a message describing the command line options and exits.
+fn usage() -> ! {
+    eprintln!(
+        "Usage: cargo run -p add_uefi_boot -- [{FW_CFG_OPTION} FILE]... \
+         [{BOOT_REPORT_OPTION} FILE] [{DEBUGCON_OPTION} FILE] [{SERIAL_OPTION} FILE] \
+         [{BIOS_OPTION}] [{NO_RUN_OPTION}] [{BOOT_FILE_OPTION} FILE]... [{DATA_DISK_OPTION} DIR] \
+         [{PXE_OPTION} DIR] [{NO_KVM_OPTION}] [{MACHINE_OPTION} TYPE] [{SMP_OPTION} CPUS] \
+         [{MEMORY_OPTION} SIZE] [{QMP_OPTION} SOCKET] [{OVERLAY_OPTION} FILE] \
+         [{LOADVM_OPTION} NAME]"
+    );
+    process::exit(1);
+}
+
+/// Copies `qemu_output` to stdout until QEMU exits, and writes the lines of the kernel's boot time
+/// report to `report`. Each line is written as soon as it is output, because the kernel never
+/// exits, so QEMU only exits when it is closed by the user. Bytes that aren't valid UTF-8 are
+/// replaced, as the kernel can send anything to the debugging console.
+fn save_boot_report(qemu_output: impl io::Read, mut report: File) {
+    let mut qemu_output = BufReader::new(qemu_output);
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        let len = qemu_output
+            .read_until(b'\n', &mut buf)
+            .expect("Failed to read qemu's output");
+        if len == 0 {
+            break;
+        }
+
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\r', '\n']);
+        println!("{line}");
+
+        if line.starts_with(BOOT_REPORT_PREFIX) {
+            writeln!(report, "{line}")
+                .and_then(|()| report.flush())
+                .expect("Failed to write the boot time report");
+        }
+    }
+}
+
+/// Adds a QEMU character device named `id` to `cmd`, which writes to `file`, or to stdio if `file`
+/// is `None`. Returns the value of a QEMU argument, e.g., `-serial`, that sends a device's output
+/// to it. The device is defined with `-chardev`, as the shorter `file:` form can't escape the path.
+fn add_chardev(cmd: &mut Command, id: &str, file: Option<&Path>) -> String {
+    let backend = match file {
+        Some(file) => format!(
+            "file,path={}",
+            escape_option_value(&file.display().to_string())
+        ),
+        None => String::from("stdio"),
+    };
+    cmd.arg("-chardev").arg(format!("{backend},id={id}"));
+    format!("chardev:{id}")
+}
+
+/// Returns the value of the QEMU `-fw_cfg` argument that passes `file` to the kernel. Exits if
+/// `file` has no file name, or the resulting fw_cfg name is too long for QEMU's file directory.
+fn fw_cfg_arg(file: &Path) -> String {
+    let Some(file_name) = file.file_name() else {
+        eprintln!("'{}' is not a file", file.display());
+        process::exit(1);
+    };
+    let name = format!("{FW_CFG_FILE_PREFIX}{}", file_name.to_string_lossy());
+
+    if name.len() > FW_CFG_MAX_NAME_LEN {
+        eprintln!("The fw_cfg name '{name}' is longer than {FW_CFG_MAX_NAME_LEN} characters");
+        process::exit(1);
+    }
+
+    format!(
+        "name={},file={}",
+        escape_option_value(&name),
+        escape_option_value(&file.display().to_string())
+    )
+}
+
+/// Returns `value` with each comma doubled, which QEMU requires for a comma in the value of a
+/// suboption, e.g., a path in `-fw_cfg name=NAME,file=PATH`. Otherwise the comma would end the
+/// value, and the rest would be parsed as another suboption.
+fn escape_option_value(value: &str) -> String {
+    value.replace(',', ",,")
+}
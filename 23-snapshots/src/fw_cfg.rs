@@ -0,0 +1,259 @@
+//! Reads data supplied by the host through QEMU's firmware configuration (fw_cfg) interface.
+//!
+//! QEMU exposes a set of numbered items, each selected by a 16-bit key, and a directory of named
+//! files which includes any passed on QEMU's command line with `-fw_cfg name=opt/...,file=...`.
+//! Items can be read a byte at a time through an I/O port or, if QEMU supports it, copied directly
+//! into memory with DMA. The interface is documented at
+//! <https://www.qemu.org/docs/master/specs/fw_cfg.html>.
+
+use crate::memory;
+use crate::trace::Category;
+use crate::trace_event;
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+use simpleos_kernel::arch::portio::{Port, PortBlock};
+use simpleos_kernel::sync::Mutex;
+use x86_64::instructions::port::{ReadOnlyAccess, WriteOnlyAccess};
+use x86_64::VirtAddr;
+
+// The fw_cfg ports, and the offset of each within them.
+const PORTS: PortBlock = unsafe { PortBlock::new(0x510, 12) };
+const SELECTOR_OFFSET: u16 = 0;
+const DATA_OFFSET: u16 = 1;
+const DMA_ADDRESS_HIGH_OFFSET: u16 = 4;
+const DMA_ADDRESS_LOW_OFFSET: u16 = 8;
+
+const SIGNATURE_KEY: u16 = 0x0000;
+const ID_KEY: u16 = 0x0001;
+const FILE_DIR_KEY: u16 = 0x0019;
+
+const SIGNATURE: [u8; 4] = *b"QEMU";
+const ID_DMA_SUPPORTED: u32 = 1 << 1;
+
+const DMA_CONTROL_ERROR: u32 = 0x01;
+const DMA_CONTROL_READ: u32 = 0x02;
+const DMA_CONTROL_SELECT: u32 = 0x08;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// The maximum length of a file name in the fw_cfg file directory, including the terminating NUL.
+pub const FILE_NAME_LEN: usize = 56;
+
+/// The prefix of the names given to files passed to the kernel by `add_uefi_boot`'s `--fw-cfg`
+/// option. This must match `FW_CFG_FILE_PREFIX` in add_uefi_boot/src/main.rs.
+pub const HOST_FILE_PREFIX: &str = "opt/simpleos/";
+
+/// A single instance of the fw_cfg interface, protected against multiple accesses by a
+/// spinlock-based `Mutex`. The lock must be held across selecting an item and reading it.
+pub static FW_CFG: Mutex<FwCfg> = Mutex::new(FwCfg::new());
+
+/// An entry in QEMU's fw_cfg file directory.
+#[derive(Clone, Copy)]
+pub struct FwCfgFile {
+    size: u32,
+    select: u16,
+    name: [u8; FILE_NAME_LEN],
+}
+
+impl FwCfgFile {
+    /// Returns the file's name, e.g., "opt/simpleos/test.txt", or "?" if it is not valid UTF-8.
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(FILE_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn size(&self) -> usize {
+        self.size as usize
+    }
+}
+
+/// The structure QEMU reads to perform a DMA transfer. All fields are big-endian. The alignment
+/// ensures the structure never crosses a page boundary, so it is physically contiguous.
+#[repr(C, align(16))]
+struct DmaAccess {
+    control: u32,
+    length: u32,
+    address: u64,
+}
+
+pub struct FwCfg {
+    selector: Port<u16, WriteOnlyAccess>,
+    data: Port<u8, ReadOnlyAccess>,
+    dma_address_high: Port<u32, WriteOnlyAccess>,
+    dma_address_low: Port<u32, WriteOnlyAccess>,
+    present: bool,
+    dma_supported: bool,
+}
+
+impl FwCfg {
+    const fn new() -> Self {
+        Self {
+            selector: PORTS.port(SELECTOR_OFFSET),
+            data: PORTS.port(DATA_OFFSET),
+            dma_address_high: PORTS.port(DMA_ADDRESS_HIGH_OFFSET),
+            dma_address_low: PORTS.port(DMA_ADDRESS_LOW_OFFSET),
+            present: false,
+            dma_supported: false,
+        }
+    }
+
+    /// Checks whether the fw_cfg interface is present by reading its signature, and if so, whether
+    /// it supports DMA. Returns `true` if the interface is present. This must be called before any
+    /// other method, all of which behave as if no items exist if the interface is not present.
+    pub fn probe(&mut self) -> bool {
+        let mut signature = [0; 4];
+        self.select(SIGNATURE_KEY);
+        self.read_bytes(&mut signature);
+        self.present = signature == SIGNATURE;
+
+        if self.present {
+            let mut id = [0; 4];
+            self.select(ID_KEY);
+            self.read_bytes(&mut id);
+            self.dma_supported = u32::from_le_bytes(id) & ID_DMA_SUPPORTED != 0;
+        }
+
+        trace_event!(
+            Category::FwCfg,
+            "Probed: present {}, DMA supported {}",
+            self.present,
+            self.dma_supported
+        );
+        self.present
+    }
+
+    /// Returns `true` if file contents are read using DMA rather than byte by byte.
+    pub fn dma_supported(&self) -> bool {
+        self.dma_supported
+    }
+
+    /// Calls `f` once for each entry in the fw_cfg file directory.
+    pub fn for_each_file<F: FnMut(&FwCfgFile)>(&mut self, mut f: F) {
+        if !self.present {
+            return;
+        }
+
+        let mut count = [0; 4];
+        self.select(FILE_DIR_KEY);
+        self.read_bytes(&mut count);
+
+        for _ in 0..u32::from_be_bytes(count) {
+            let mut size = [0; 4];
+            let mut select = [0; 2];
+            let mut reserved = [0; 2];
+            let mut name = [0; FILE_NAME_LEN];
+            self.read_bytes(&mut size);
+            self.read_bytes(&mut select);
+            self.read_bytes(&mut reserved);
+            self.read_bytes(&mut name);
+
+            f(&FwCfgFile {
+                size: u32::from_be_bytes(size),
+                select: u16::from_be_bytes(select),
+                name,
+            });
+        }
+    }
+
+    /// Reads the start of `file` into `buf`, returning the number of bytes read. This is the
+    /// smaller of the file size and the buffer size.
+    ///
+    /// Panics if QEMU reports an error in a DMA transfer.
+    pub fn read_file(&mut self, file: &FwCfgFile, buf: &mut [u8]) -> usize {
+        if !self.present {
+            return 0;
+        }
+
+        let len = file.size().min(buf.len());
+        if self.dma_supported {
+            self.dma_read(file.select, &mut buf[..len]);
+        } else {
+            self.select(file.select);
+            self.read_bytes(&mut buf[..len]);
+        }
+
+        trace_event!(Category::FwCfg, "Read {len} bytes of {}", file.name());
+        len
+    }
+
+    /// Selects the item to be read by subsequent reads from the data port, and resets the read
+    /// offset to the start of the item.
+    fn select(&mut self, key: u16) {
+        self.selector.write(key);
+    }
+
+    /// Fills `buf` from the data port, continuing from where the previous read of the selected
+    /// item finished.
+    fn read_bytes(&mut self, buf: &mut [u8]) {
+        for b in buf.iter_mut() {
+            *b = self.data.read();
+        }
+    }
+
+    /// Selects the item with the given key and reads the start of it into `buf` using DMA.
+    ///
+    /// QEMU copies data to physical addresses, but a buffer that is contiguous in virtual memory
+    /// may span several physical frames that are not. The buffer is therefore transferred in pieces
+    /// that each lie within a single page. Only the first transfer selects the item, so the
+    /// remaining ones continue from where the previous transfer finished.
+    fn dma_read(&mut self, key: u16, buf: &mut [u8]) {
+        let mut control = DMA_CONTROL_SELECT | DMA_CONTROL_READ | (u32::from(key) << 16);
+        let mut offset = 0;
+
+        while offset < buf.len() {
+            let chunk = &mut buf[offset..];
+            let virt_addr = VirtAddr::from_ptr(chunk.as_mut_ptr());
+            let to_page_end = (PAGE_SIZE - virt_addr.as_u64() % PAGE_SIZE) as usize;
+            let len = to_page_end.min(chunk.len());
+            let phys_addr =
+                memory::translate_addr(virt_addr).expect("fw_cfg DMA buffer is not mapped");
+
+            self.dma_transfer(control, phys_addr.as_u64(), len as u32);
+            control = DMA_CONTROL_READ;
+            offset += len;
+        }
+    }
+
+    /// Performs a single DMA transfer of `len` bytes to or from physical address `phys_addr`, and
+    /// waits for QEMU to complete it.
+    fn dma_transfer(&mut self, control: u32, phys_addr: u64, len: u32) {
+        let mut access = DmaAccess {
+            control: control.to_be(),
+            length: len.to_be(),
+            address: phys_addr.to_be(),
+        };
+        // QEMU writes the status back to the structure, so after it is initialized it is only
+        // accessed through this raw pointer, never through a reference.
+        let access_ptr = ptr::addr_of_mut!(access);
+        let access_addr = memory::translate_addr(VirtAddr::from_ptr(access_ptr))
+            .expect("fw_cfg DMA access structure is not mapped")
+            .as_u64();
+
+        // Make sure the structure is in memory before QEMU is told where to find it. The write to
+        // the low half of the address starts the transfer.
+        fence(Ordering::SeqCst);
+        self.dma_address_high
+            .write(((access_addr >> 32) as u32).to_be());
+        self.dma_address_low.write((access_addr as u32).to_be());
+
+        // QEMU clears every bit except the error bit once the transfer is complete.
+        let status = loop {
+            let status =
+                u32::from_be(unsafe { ptr::read_volatile(ptr::addr_of!((*access_ptr).control)) });
+            if status & !DMA_CONTROL_ERROR == 0 {
+                break status;
+            }
+        };
+        fence(Ordering::SeqCst);
+
+        assert!(
+            status & DMA_CONTROL_ERROR == 0,
+            "fw_cfg DMA transfer failed"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Configures how the bootloader sets up the environment the kernel runs in, and checks that the
+//! bootloader honoured the configuration.
+
+use crate::bootinfo::{BootInfo, MemoryKind};
+use bootloader_api::config::{BootloaderConfig, Mapping};
+use simpleos_kernel::fmtutil::ByteSize;
+use simpleos_kernel::{eprintln, println};
+
+/// Virtual address at which the bootloader is asked to map the whole of physical memory. Adding
+/// this offset to any physical address gives a virtual address the kernel can use to access it.
+pub const PHYSICAL_MEMORY_OFFSET: u64 = 0x0000_4000_0000_0000;
+
+/// Size of the kernel stack requested from the bootloader, which otherwise defaults to 80 KiB.
+pub const KERNEL_STACK_SIZE: u64 = 256 * 1024;
+
+/// Minimum framebuffer width requested, in pixels. The bootloader reads this from the boot
+/// configuration stored in the disk image by `add_uefi_boot`, not from `BOOTLOADER_CONFIG`, so this
+/// value must match `MINIMUM_FRAMEBUFFER_WIDTH` in add_uefi_boot/src/main.rs.
+pub const MINIMUM_FRAMEBUFFER_WIDTH: usize = 1024;
+
+/// Minimum framebuffer height requested, in pixels. This must match `MINIMUM_FRAMEBUFFER_HEIGHT`
+/// in add_uefi_boot/src/main.rs.
+pub const MINIMUM_FRAMEBUFFER_HEIGHT: usize = 768;
+
+/// The configuration passed to the bootloader. The `entry_point!` macro serializes this into a
+/// dedicated section of the kernel executable, where the bootloader finds it when loading the
+/// kernel.
+pub static BOOTLOADER_CONFIG: BootloaderConfig = {
+    let mut config = BootloaderConfig::new_default();
+    config.mappings.physical_memory = Some(Mapping::FixedAddress(PHYSICAL_MEMORY_OFFSET));
+    config.kernel_stack_size = KERNEL_STACK_SIZE;
+    config
+};
+
+/// Outputs the information passed by the bootloader, including each configuration value requested
+/// from the bootloader alongside the value the bootloader actually provided, then checks them.
+///
+/// Panics if physical memory is not mapped at the requested offset or the kernel stack is smaller
+/// than requested, as later code relies on both. A smaller framebuffer is only reported because
+/// the bootloader is permitted to fall back to a lower resolution if the requested one is not
+/// available.
+pub fn log_and_validate(boot_info: &BootInfo) {
+    println!(
+        "Boot protocol: {} version {}",
+        boot_info.protocol, boot_info.protocol_version
+    );
+
+    let memory_size = |kind| {
+        boot_info
+            .memory_regions()
+            .filter(|region| region.kind == kind)
+            .map(|region| region.end - region.start)
+            .sum::<u64>()
+    };
+    println!(
+        "Physical memory: {} usable, {} used by bootloader, {} reserved",
+        ByteSize(memory_size(MemoryKind::Usable)),
+        ByteSize(memory_size(MemoryKind::Bootloader)),
+        ByteSize(memory_size(MemoryKind::Reserved))
+    );
+    if boot_info.dropped_memory_regions > 0 {
+        eprintln!(
+            "Memory map is too large, {} regions ignored",
+            boot_info.dropped_memory_regions
+        );
+    }
+
+    match boot_info.physical_memory_offset {
+        Some(offset) => println!(
+            "Physical memory offset: requested {PHYSICAL_MEMORY_OFFSET:#x}, provided {offset:#x}"
+        ),
+        None => {
+            println!("Physical memory offset: requested {PHYSICAL_MEMORY_OFFSET:#x}, not mapped")
+        }
+    }
+
+    println!(
+        "Kernel stack size: requested {KERNEL_STACK_SIZE} bytes, provided {} bytes at {:#x}",
+        boot_info.kernel_stack_len, boot_info.kernel_stack_bottom
+    );
+
+    match boot_info.framebuffer {
+        Some(fb) => println!(
+            "Framebuffer: requested at least {MINIMUM_FRAMEBUFFER_WIDTH}x\
+            {MINIMUM_FRAMEBUFFER_HEIGHT}, provided {}x{} with stride {} and {} bytes per pixel, \
+            {} at {:#x}",
+            fb.width,
+            fb.height,
+            fb.stride,
+            fb.bytes_per_pixel,
+            ByteSize(fb.len as u64),
+            fb.addr
+        ),
+        None => println!(
+            "Framebuffer: requested at least {MINIMUM_FRAMEBUFFER_WIDTH}x\
+            {MINIMUM_FRAMEBUFFER_HEIGHT}, none provided"
+        ),
+    }
+
+    match boot_info.rsdp_addr {
+        Some(addr) => println!("ACPI RSDP at {addr:#x}"),
+        None => println!("ACPI RSDP not found"),
+    }
+
+    match boot_info.cmdline {
+        Some(cmdline) => println!("Command line: {cmdline}"),
+        None => println!("Command line: none provided"),
+    }
+
+    assert_eq!(
+        boot_info.physical_memory_offset,
+        Some(PHYSICAL_MEMORY_OFFSET),
+        "bootloader did not map physical memory at the requested offset"
+    );
+    assert!(
+        boot_info.kernel_stack_len >= KERNEL_STACK_SIZE,
+        "bootloader provided a smaller kernel stack than requested"
+    );
+
+    if let Some(fb) = boot_info.framebuffer {
+        if fb.width < MINIMUM_FRAMEBUFFER_WIDTH || fb.height < MINIMUM_FRAMEBUFFER_HEIGHT {
+            eprintln!("Framebuffer is smaller than requested");
+        }
+    }
+}
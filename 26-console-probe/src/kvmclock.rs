@@ -0,0 +1,213 @@
+//! Reads the time from KVM's paravirtual clock, kvmclock.
+//!
+//! The time stamp counter's frequency is not architecturally defined, so converting cycles to
+//! nanoseconds normally requires measuring it against another timer. In a virtual machine the
+//! measurement is unreliable, because the virtual CPU may not run while it is taken. Instead, KVM
+//! writes the counter's frequency, as a multiplier and shift, to a structure in the kernel's memory
+//! once the kernel tells it the structure's physical address through an MSR. The structure also
+//! contains the time since the host booted at a given counter value, which gives the current time
+//! when combined with the counter. A second MSR makes KVM write the wall clock time at which the
+//! host booted.
+//!
+//! KVM updates the structures at any time, incrementing a version number before and after each
+//! update, so the version is odd during an update. They are read until the version is even and
+//! unchanged by the read. The protocol is described at
+//! <https://docs.kernel.org/virt/kvm/x86/msr.html>.
+
+use crate::hypervisor::{Hypervisor, CPUID_HYPERVISOR_BASE_LEAF};
+use crate::memory;
+use core::arch::x86_64::{__cpuid, _rdtsc};
+use core::cell::UnsafeCell;
+use core::ptr::{self, addr_of};
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::registers::model_specific::Msr;
+use x86_64::VirtAddr;
+
+/// The CPUID leaf, relative to the start of the hypervisor range, that reports KVM's features,
+/// and the feature bit reporting support for the MSRs below.
+const CPUID_KVM_FEATURES_OFFSET: u32 = 1;
+const CPUID_KVM_FEATURES_EAX_CLOCKSOURCE2: u32 = 1 << 3;
+
+// The MSRs through which the physical addresses of the wall clock and time structures are passed
+// to KVM, and the bit in the latter that enables updates.
+const MSR_KVM_WALL_CLOCK_NEW: u32 = 0x4b56_4d00;
+const MSR_KVM_SYSTEM_TIME_NEW: u32 = 0x4b56_4d01;
+const SYSTEM_TIME_ENABLE: u64 = 1;
+
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Set once KVM has been told the address of `TIME_INFO`, after which it keeps it up to date.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The time structure KVM updates, and the wall clock structure it writes when asked.
+static TIME_INFO: HypervisorShared<VcpuTimeInfo> =
+    HypervisorShared(UnsafeCell::new(VcpuTimeInfo {
+        version: 0,
+        _pad0: 0,
+        tsc_timestamp: 0,
+        system_time: 0,
+        tsc_to_system_mul: 0,
+        tsc_shift: 0,
+        flags: 0,
+        _pad: [0; 2],
+    }));
+static WALL_CLOCK: HypervisorShared<WallClock> = HypervisorShared(UnsafeCell::new(WallClock {
+    version: 0,
+    sec: 0,
+    nsec: 0,
+}));
+
+/// KVM's `pvclock_vcpu_time_info` structure. The time since the host booted is `system_time`
+/// nanoseconds at time stamp counter value `tsc_timestamp`. A number of cycles is converted to
+/// nanoseconds by shifting it left by `tsc_shift`, or right if negative, then multiplying it by
+/// `tsc_to_system_mul` and dividing by 2^32. The alignment ensures the structure never crosses a
+/// page boundary, so it is physically contiguous.
+#[repr(C, align(32))]
+struct VcpuTimeInfo {
+    version: u32,
+    _pad0: u32,
+    tsc_timestamp: u64,
+    system_time: u64,
+    tsc_to_system_mul: u32,
+    tsc_shift: i8,
+    flags: u8,
+    _pad: [u8; 2],
+}
+
+/// KVM's `pvclock_wall_clock` structure, containing the time at which the host booted, in seconds
+/// and nanoseconds since the Unix epoch.
+#[repr(C, align(16))]
+struct WallClock {
+    version: u32,
+    sec: u32,
+    nsec: u32,
+}
+
+/// A structure written by KVM, which the kernel only reads.
+struct HypervisorShared<T>(UnsafeCell<T>);
+
+// The kernel never writes to the structure, and reads it with volatile reads checked by its
+// version number.
+unsafe impl<T> Sync for HypervisorShared<T> {}
+
+impl<T> HypervisorShared<T> {
+    /// Returns the physical address of the structure.
+    fn phys_addr(&self) -> u64 {
+        memory::translate_addr(VirtAddr::from_ptr(self.0.get()))
+            .expect("kvmclock structure is not mapped")
+            .as_u64()
+    }
+}
+
+/// Enables kvmclock if `hypervisor` is KVM and supports it. Returns `true` if it was enabled. This
+/// must be called before any other function in this module, all of which return `None` if
+/// kvmclock is not enabled.
+pub fn init(hypervisor: &Hypervisor) -> bool {
+    let features_leaf = CPUID_HYPERVISOR_BASE_LEAF + CPUID_KVM_FEATURES_OFFSET;
+    if !hypervisor.is_kvm()
+        || hypervisor.max_leaf() < features_leaf
+        || __cpuid(features_leaf).eax & CPUID_KVM_FEATURES_EAX_CLOCKSOURCE2 == 0
+    {
+        return false;
+    }
+
+    // KVM writes to the structures at the physical addresses given, which belong to statics that
+    // live for as long as the kernel.
+    unsafe {
+        Msr::new(MSR_KVM_SYSTEM_TIME_NEW).write(TIME_INFO.phys_addr() | SYSTEM_TIME_ENABLE);
+        Msr::new(MSR_KVM_WALL_CLOCK_NEW).write(WALL_CLOCK.phys_addr());
+    }
+
+    ENABLED.store(true, Ordering::Release);
+    true
+}
+
+/// Returns the number of nanoseconds since the host booted, which only ever increases.
+pub fn nanoseconds() -> Option<u64> {
+    read_time_info(|time_info| {
+        let cycles = unsafe { _rdtsc() }.wrapping_sub(time_info.tsc_timestamp);
+        time_info.system_time.wrapping_add(scale(cycles, time_info))
+    })
+}
+
+/// Returns the number of nanoseconds that `cycles` cycles of the time stamp counter take.
+pub fn cycles_to_nanoseconds(cycles: u64) -> Option<u64> {
+    read_time_info(|time_info| scale(cycles, time_info))
+}
+
+/// Returns the current wall clock time, in seconds and nanoseconds since the Unix epoch.
+pub fn wall_clock() -> Option<(u64, u32)> {
+    let now = nanoseconds()?;
+
+    let boot = loop {
+        let wall_clock = WALL_CLOCK.0.get();
+        let version = unsafe { ptr::read_volatile(addr_of!((*wall_clock).version)) };
+        let sec = unsafe { ptr::read_volatile(addr_of!((*wall_clock).sec)) };
+        let nsec = unsafe { ptr::read_volatile(addr_of!((*wall_clock).nsec)) };
+        if version % 2 == 0
+            && version == unsafe { ptr::read_volatile(addr_of!((*wall_clock).version)) }
+        {
+            break u64::from(sec) * NANOSECONDS_PER_SECOND + u64::from(nsec);
+        }
+    };
+
+    let time = boot + now;
+    Some((
+        time / NANOSECONDS_PER_SECOND,
+        (time % NANOSECONDS_PER_SECOND) as u32,
+    ))
+}
+
+/// The fields of `VcpuTimeInfo` needed to calculate the time.
+struct TimeInfo {
+    tsc_timestamp: u64,
+    system_time: u64,
+    tsc_to_system_mul: u32,
+    tsc_shift: i8,
+}
+
+/// Calls `f` with a consistent copy of the fields of `TIME_INFO`, and returns its result, or `None`
+/// if kvmclock is not enabled. `f` may be called more than once, if KVM updates the structure
+/// while it runs.
+fn read_time_info<R, F: Fn(&TimeInfo) -> R>(f: F) -> Option<R> {
+    if !ENABLED.load(Ordering::Acquire) {
+        return None;
+    }
+
+    let time_info = TIME_INFO.0.get();
+    loop {
+        let version = unsafe { ptr::read_volatile(addr_of!((*time_info).version)) };
+        if version % 2 != 0 {
+            continue;
+        }
+
+        // The structure is only written by KVM, so volatile reads are used to stop the compiler
+        // assuming its fields are unchanged between reads.
+        let copy = unsafe {
+            TimeInfo {
+                tsc_timestamp: ptr::read_volatile(addr_of!((*time_info).tsc_timestamp)),
+                system_time: ptr::read_volatile(addr_of!((*time_info).system_time)),
+                tsc_to_system_mul: ptr::read_volatile(addr_of!((*time_info).tsc_to_system_mul)),
+                tsc_shift: ptr::read_volatile(addr_of!((*time_info).tsc_shift)),
+            }
+        };
+        let result = f(&copy);
+
+        if version == unsafe { ptr::read_volatile(addr_of!((*time_info).version)) } {
+            return Some(result);
+        }
+    }
+}
+
+/// Converts `cycles` cycles of the time stamp counter to nanoseconds, using the multiplier and
+/// shift in `time_info`.
+fn scale(cycles: u64, time_info: &TimeInfo) -> u64 {
+    let shift = u32::from(time_info.tsc_shift.unsigned_abs());
+    let cycles = if time_info.tsc_shift >= 0 {
+        cycles << shift
+    } else {
+        cycles >> shift
+    };
+
+    ((u128::from(cycles) * u128::from(time_info.tsc_to_system_mul)) >> 32) as u64
+}
@@ -0,0 +1,87 @@
+//! Measures how long each stage of kernel initialization takes.
+//!
+//! Each stage is run by passing it to `stage()`, which records the time stamp counter before and
+//! after it runs. `report()` then outputs the time taken by each stage in cycles, one stage per
+//! line, with each line starting with `REPORT_PREFIX` so that `add_uefi_boot` can extract the
+//! report from the rest of the kernel's output and save it for comparison with later runs. When
+//! kvmclock is enabled, each line also includes the time taken in microseconds, which KVM's
+//! measurement of the time stamp counter's frequency makes comparable between hosts.
+
+use crate::kvmclock;
+use core::arch::x86_64::_rdtsc;
+use simpleos_kernel::println;
+use simpleos_kernel::sync::Mutex;
+
+/// The maximum number of stages that are recorded. Any further stages are run but not recorded.
+const MAX_STAGES: usize = 16;
+
+/// The text at the start of each line of the report. This must match `BOOT_REPORT_PREFIX` in
+/// add_uefi_boot/src/main.rs.
+const REPORT_PREFIX: &str = "boot-time:";
+
+/// The stages recorded so far, protected against multiple accesses by a spinlock-based `Mutex`.
+static STAGES: Mutex<Stages> = Mutex::new(Stages {
+    stages: [None; MAX_STAGES],
+    count: 0,
+});
+
+#[derive(Clone, Copy)]
+struct Stage {
+    name: &'static str,
+    start: u64,
+    end: u64,
+}
+
+struct Stages {
+    stages: [Option<Stage>; MAX_STAGES],
+    count: usize,
+}
+
+/// Runs `f` as the initialization stage called `name`, recording the time it takes, and returns
+/// its result. The lock on the recorded stages is not held while `f` runs, so stages can be
+/// nested, although the time taken by an inner stage is then also included in the outer one.
+pub fn stage<R, F: FnOnce() -> R>(name: &'static str, f: F) -> R {
+    let start = unsafe { _rdtsc() };
+    let result = f();
+    let end = unsafe { _rdtsc() };
+
+    let mut stages = STAGES.lock();
+    if stages.count < MAX_STAGES {
+        let index = stages.count;
+        stages.stages[index] = Some(Stage { name, start, end });
+        stages.count += 1;
+    }
+
+    result
+}
+
+/// Outputs the time taken by each stage recorded so far, followed by the total time from the
+/// start of the first stage to the end of the last.
+pub fn report() {
+    let stages = STAGES.lock();
+    let recorded = || stages.stages.iter().flatten();
+
+    if kvmclock::cycles_to_nanoseconds(0).is_some() {
+        println!("Boot time by stage, in time stamp counter cycles and microseconds:");
+    } else {
+        println!("Boot time by stage, in time stamp counter cycles:");
+    }
+    for stage in recorded() {
+        report_line(stage.name, stage.end - stage.start);
+    }
+
+    let start = recorded().map(|stage| stage.start).min().unwrap_or(0);
+    let end = recorded().map(|stage| stage.end).max().unwrap_or(0);
+    report_line("total", end - start);
+}
+
+/// Outputs a line of the report for the stage called `name`, which took `cycles` cycles.
+fn report_line(name: &str, cycles: u64) {
+    match kvmclock::cycles_to_nanoseconds(cycles) {
+        Some(nanoseconds) => println!(
+            "{REPORT_PREFIX} {name:<12} {cycles:>12} {:>10}",
+            nanoseconds / 1000
+        ),
+        None => println!("{REPORT_PREFIX} {name:<12} {cycles:>12}"),
+    }
+}
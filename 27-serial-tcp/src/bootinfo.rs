@@ -0,0 +1,188 @@
+//! Information passed to the kernel by the bootloader, in a form that doesn't depend on the
+//! bootloader used.
+//!
+//! `BootInfo::from_bootloader()` is the only code in the kernel that reads the `bootloader_api`
+//! crate's `BootInfo`. It copies everything the kernel uses into the types in this module, so
+//! `BootInfo` holds no `bootloader_api` types, and supporting another boot protocol only requires
+//! another function that creates a `BootInfo`.
+
+use core::fmt::{self, Display};
+
+/// The maximum number of regions of physical memory `BootInfo` holds. Adjacent regions of the same
+/// kind are merged as they are added, so far fewer are needed than a firmware memory map contains.
+pub const MAX_MEMORY_REGIONS: usize = 64;
+
+/// The information passed to the kernel by the bootloader.
+pub struct BootInfo {
+    /// The name of the boot protocol used to load the kernel.
+    pub protocol: &'static str,
+
+    /// The version of the boot protocol.
+    pub protocol_version: Version,
+
+    /// The virtual address at which all of physical memory is mapped, if it is mapped.
+    pub physical_memory_offset: Option<u64>,
+
+    /// The virtual address of the lowest byte of the kernel stack.
+    pub kernel_stack_bottom: u64,
+
+    /// The size of the kernel stack in bytes.
+    pub kernel_stack_len: u64,
+
+    /// The framebuffer, if the bootloader set one up.
+    pub framebuffer: Option<FramebufferInfo>,
+
+    /// The physical address of the ACPI RSDP (Root System Description Pointer) structure, if the
+    /// bootloader found it.
+    pub rsdp_addr: Option<u64>,
+
+    /// The kernel command line, if the boot protocol provides one.
+    pub cmdline: Option<&'static str>,
+
+    /// The number of regions of physical memory that didn't fit in `MAX_MEMORY_REGIONS`, and are
+    /// ignored.
+    pub dropped_memory_regions: usize,
+
+    memory_regions: [MemoryRegion; MAX_MEMORY_REGIONS],
+    memory_region_count: usize,
+}
+
+/// A version number in the form major.minor.patch.
+#[derive(Clone, Copy, Debug)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// A range of physical memory, from `start` up to but not including `end`.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryRegion {
+    pub start: u64,
+    pub end: u64,
+    pub kind: MemoryKind,
+}
+
+/// How a region of physical memory is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// Free memory that the kernel may use.
+    Usable,
+
+    /// Memory the bootloader used to load the kernel, e.g., for the kernel's code, page tables or
+    /// stack. This is in use.
+    Bootloader,
+
+    /// Memory reserved by the firmware or hardware, which the kernel must not use.
+    Reserved,
+}
+
+/// The size and layout of the framebuffer.
+#[derive(Clone, Copy, Debug)]
+pub struct FramebufferInfo {
+    /// The virtual address of the first byte of the framebuffer.
+    pub addr: u64,
+
+    /// The size of the framebuffer in bytes.
+    pub len: usize,
+
+    /// The width of the visible area in pixels.
+    pub width: usize,
+
+    /// The height of the visible area in pixels.
+    pub height: usize,
+
+    /// The number of pixels between the start of one line and the start of the next, which may be
+    /// larger than `width`.
+    pub stride: usize,
+
+    /// The number of bytes used to store each pixel.
+    pub bytes_per_pixel: usize,
+}
+
+impl BootInfo {
+    /// Creates a `BootInfo` from the information passed by the `bootloader` crate.
+    pub fn from_bootloader(boot_info: &'static bootloader_api::BootInfo) -> Self {
+        let api = &boot_info.api_version;
+
+        let mut info = Self {
+            protocol: "bootloader_api",
+            protocol_version: Version {
+                major: api.version_major(),
+                minor: api.version_minor(),
+                patch: api.version_patch(),
+            },
+            physical_memory_offset: boot_info.physical_memory_offset.into_option(),
+            kernel_stack_bottom: boot_info.kernel_stack_bottom,
+            kernel_stack_len: boot_info.kernel_stack_len,
+            framebuffer: boot_info.framebuffer.as_ref().map(|fb| {
+                let info = fb.info();
+                FramebufferInfo {
+                    addr: fb.buffer().as_ptr() as u64,
+                    len: info.byte_len,
+                    width: info.width,
+                    height: info.height,
+                    stride: info.stride,
+                    bytes_per_pixel: info.bytes_per_pixel,
+                }
+            }),
+            rsdp_addr: boot_info.rsdp_addr.into_option(),
+            // The bootloader crate doesn't support passing a command line to the kernel.
+            cmdline: None,
+            dropped_memory_regions: 0,
+            memory_regions: [MemoryRegion {
+                start: 0,
+                end: 0,
+                kind: MemoryKind::Reserved,
+            }; MAX_MEMORY_REGIONS],
+            memory_region_count: 0,
+        };
+
+        for region in boot_info.memory_regions.iter() {
+            info.add_memory_region(MemoryRegion {
+                start: region.start,
+                end: region.end,
+                kind: match region.kind {
+                    bootloader_api::info::MemoryRegionKind::Usable => MemoryKind::Usable,
+                    bootloader_api::info::MemoryRegionKind::Bootloader => MemoryKind::Bootloader,
+                    _ => MemoryKind::Reserved,
+                },
+            });
+        }
+
+        info
+    }
+
+    /// Returns an iterator over the regions of physical memory described by the bootloader.
+    pub fn memory_regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        self.memory_regions[..self.memory_region_count]
+            .iter()
+            .copied()
+    }
+
+    /// Adds `region` to the memory map, merging it with the previous region if it is the same kind
+    /// and starts where that region ends. If the memory map is full, the region is counted in
+    /// `dropped_memory_regions` instead.
+    fn add_memory_region(&mut self, region: MemoryRegion) {
+        if let Some(last) = self.memory_regions[..self.memory_region_count].last_mut() {
+            if last.kind == region.kind && last.end == region.start {
+                last.end = region.end;
+                return;
+            }
+        }
+
+        match self.memory_regions.get_mut(self.memory_region_count) {
+            Some(slot) => {
+                *slot = region;
+                self.memory_region_count += 1;
+            }
+            None => self.dropped_memory_regions += 1,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Detects whether the kernel is running in a virtual machine, and if so, which hypervisor runs it.
+//!
+//! CPUs set a bit in CPUID leaf 1 when a hypervisor is present, and hypervisors describe
+//! themselves in the range of CPUID leaves starting at `CPUID_HYPERVISOR_BASE_LEAF`. The first of
+//! these contains the highest leaf in the range, and a 12-byte signature identifying the vendor.
+
+use core::arch::x86_64::__cpuid;
+
+// The CPUID leaf containing the hypervisor present bit, and the bit itself.
+const CPUID_FEATURES_LEAF: u32 = 1;
+const CPUID_FEATURES_ECX_HYPERVISOR: u32 = 1 << 31;
+
+/// The first CPUID leaf of the range reserved for hypervisors.
+pub const CPUID_HYPERVISOR_BASE_LEAF: u32 = 0x4000_0000;
+
+/// The signatures of some common hypervisors, and their names.
+const KNOWN_HYPERVISORS: [(&[u8; 12], &str); 6] = [
+    (KVM_SIGNATURE, "KVM"),
+    (b"TCGTCGTCGTCG", "QEMU TCG"),
+    (b"Microsoft Hv", "Hyper-V"),
+    (b"VMwareVMware", "VMware"),
+    (b"VBoxVBoxVBox", "VirtualBox"),
+    (b"XenVMMXenVMM", "Xen"),
+];
+
+/// The signature of KVM, which is padded with zeros.
+const KVM_SIGNATURE: &[u8; 12] = b"KVMKVMKVM\0\0\0";
+
+/// The hypervisor running the kernel.
+#[derive(Clone, Copy)]
+pub struct Hypervisor {
+    signature: [u8; 12],
+    max_leaf: u32,
+}
+
+impl Hypervisor {
+    /// Returns the hypervisor's name if it is one of `KNOWN_HYPERVISORS`, or its signature
+    /// otherwise.
+    pub fn name(&self) -> &str {
+        KNOWN_HYPERVISORS
+            .iter()
+            .find(|(signature, _)| **signature == self.signature)
+            .map(|&(_, name)| name)
+            .unwrap_or_else(|| {
+                let len = self
+                    .signature
+                    .iter()
+                    .position(|&b| b == 0)
+                    .unwrap_or(self.signature.len());
+                core::str::from_utf8(&self.signature[..len]).unwrap_or("?")
+            })
+    }
+
+    /// Returns `true` if the hypervisor is KVM.
+    pub fn is_kvm(&self) -> bool {
+        self.signature == *KVM_SIGNATURE
+    }
+
+    /// Returns the highest CPUID leaf in the hypervisor's range.
+    pub fn max_leaf(&self) -> u32 {
+        self.max_leaf
+    }
+}
+
+/// Returns the hypervisor running the kernel, or `None` if the kernel is running directly on the
+/// hardware.
+pub fn detect() -> Option<Hypervisor> {
+    if __cpuid(CPUID_FEATURES_LEAF).ecx & CPUID_FEATURES_ECX_HYPERVISOR == 0 {
+        return None;
+    }
+
+    let leaf = __cpuid(CPUID_HYPERVISOR_BASE_LEAF);
+    let mut signature = [0; 12];
+    signature[0..4].copy_from_slice(&leaf.ebx.to_le_bytes());
+    signature[4..8].copy_from_slice(&leaf.ecx.to_le_bytes());
+    signature[8..12].copy_from_slice(&leaf.edx.to_le_bytes());
+
+    Some(Hypervisor {
+        signature,
+        max_leaf: leaf.eax,
+    })
+}
@@ -0,0 +1,142 @@
+//! Emits log records as JSON lines for automated analysis of boot runs.
+//!
+//! The kernel's output is written for people to read, so tools that check it must match its exact
+//! wording. Log records are also written, one JSON object per line, to a second QEMU debugging
+//! console at `RECORD_PORT`, which `add_uefi_boot` creates when passed `--log-records FILE`. Each
+//! record has the fields:
+//!
+//! - `tsc`, the time stamp counter when the record was emitted;
+//! - `ns`, the nanoseconds since the host booted, only when kvmclock is enabled;
+//! - `level`, one of "error", "warn", "info" and "debug";
+//! - `module`, the part of the kernel the record relates to;
+//! - `msg`, the message.
+//!
+//! Every event recorded in the trace buffer is also emitted as a record with level "debug" and
+//! the name of its category as its module. The console only exists if the runner creates it, so
+//! `probe()` checks whether it is present, and records are discarded until it finds that it is.
+
+use crate::kvmclock;
+use core::arch::x86_64::_rdtsc;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use simpleos_kernel::arch::portio::Port;
+use simpleos_kernel::sync::Mutex;
+
+/// The port of the debugging console that records are written to. This must match
+/// `LOG_RECORDS_PORT` in add_uefi_boot/src/main.rs.
+const RECORD_PORT: u16 = 0x402;
+
+/// The value read from the console's port when the console is present. QEMU returns the same
+/// value as for the first debugging console, regardless of the port.
+const CONSOLE_PRESENT_VALUE: u8 = 0xE9;
+
+/// Whether the console is present, which is assumed not to be until `probe()` finds it.
+static PRESENT: AtomicBool = AtomicBool::new(false);
+
+// The console's port, protected against multiple accesses by a spinlock-based `Mutex`, which is
+// held while a whole record is written so records are never interleaved. Accesses aren't traced,
+// as every trace event is itself written to this port.
+static RECORD_CONSOLE: Mutex<RecordConsole> = Mutex::new(RecordConsole {
+    port: unsafe { Port::new_untraced(RECORD_PORT) },
+});
+
+/// The severity of a log record.
+#[derive(Clone, Copy, Debug)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+}
+
+/// Emits a log record if the record console is present. The arguments following the level and
+/// module are the same as those of `format_args!`, e.g.,
+/// `log_record!(Level::Info, "fw_cfg", "Found {} files", count)`.
+#[macro_export]
+macro_rules! log_record {
+    ($level:expr, $module:expr, $($arg:tt)*) => {{
+        if $crate::log_record::present() {
+            $crate::log_record::_emit($level, $module, format_args!($($arg)*));
+        }
+    }};
+}
+
+/// Checks whether the record console is present by reading its port. Returns `true` if it is, in
+/// which case records are emitted from then on.
+pub fn probe() -> bool {
+    let present = RECORD_CONSOLE.lock().port.read() == CONSOLE_PRESENT_VALUE;
+    PRESENT.store(present, Ordering::Relaxed);
+    present
+}
+
+/// Returns `true` if `probe()` found the record console, so records are emitted.
+pub fn present() -> bool {
+    PRESENT.load(Ordering::Relaxed)
+}
+
+/// Writes a log record to the record console, regardless of whether it is present.
+///
+/// This function is intended only for internal use, but is declared `pub` to allow its use from
+/// macros.
+#[doc(hidden)]
+pub fn _emit(level: Level, module: &str, args: fmt::Arguments) {
+    let tsc = unsafe { _rdtsc() };
+    let mut console = RECORD_CONSOLE.lock();
+
+    write!(console, "{{\"tsc\":{tsc},").unwrap();
+    if let Some(ns) = kvmclock::nanoseconds() {
+        write!(console, "\"ns\":{ns},").unwrap();
+    }
+    write!(console, "\"level\":\"{}\",\"module\":\"", level.name()).unwrap();
+    JsonString(&mut *console).write_str(module).unwrap();
+    console.write_str("\",\"msg\":\"").unwrap();
+    JsonString(&mut *console).write_fmt(args).unwrap();
+    console.write_str("\"}\n").unwrap();
+}
+
+struct RecordConsole {
+    port: Port<u8>,
+}
+
+impl Write for RecordConsole {
+    /// Outputs the given string to the record console. This function is always successful so never
+    /// returns an error.
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        for b in s.bytes() {
+            self.port.write(b);
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes strings to the wrapped writer with the characters that JSON strings can't contain
+/// escaped.
+struct JsonString<'a, W: Write>(&'a mut W);
+
+impl<W: Write> Write for JsonString<'_, W> {
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        for c in s.chars() {
+            match c {
+                '"' => self.0.write_str("\\\"")?,
+                '\\' => self.0.write_str("\\\\")?,
+                '\n' => self.0.write_str("\\n")?,
+                c if c < ' ' => write!(self.0, "\\u{:04x}", c as u32)?,
+                c => self.0.write_char(c)?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! Stores the kernel's settings in the CMOS memory of the real-time clock (RTC).
+//!
+//! The RTC has 128 bytes of memory that keep their contents while the machine is reset, and on
+//! real hardware while it is powered off, so settings can be kept before the kernel has a disk
+//! driver. The first 14 bytes are the clock's registers and much of the rest is used by the
+//! firmware, so the settings are kept in the last `SETTINGS_LEN` bytes, which QEMU and the firmware
+//! it runs leave unused. A byte is read or written by writing its index to the index port, then
+//! accessing the data port.
+//!
+//! The settings start with a magic number and version, and end with a checksum of the other bytes,
+//! in the same way as the firmware's own settings. The memory contains whatever the firmware left
+//! there on first boot, so `load()` only returns settings that pass these checks.
+
+use crate::log_record;
+use crate::trace::Category;
+use crate::trace_event;
+use simpleos_kernel::arch::portio::{Port, PortBlock, ReadWriteAccess, WriteOnlyAccess};
+use simpleos_kernel::sync::Mutex;
+
+// The RTC's ports, and the offset of each within them.
+const CMOS_PORTS: PortBlock = unsafe { PortBlock::new(0x70, 2) };
+const INDEX_OFFSET: u16 = 0;
+const DATA_OFFSET: u16 = 1;
+
+// The index of the first byte of CMOS memory used for the settings, and the number of bytes used.
+const SETTINGS_START: u8 = 0x70;
+const SETTINGS_LEN: usize = 16;
+
+// The layout of the settings. The bytes between the record level and the checksum are reserved,
+// and are zero in this version.
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 1;
+const BOOT_COUNT_OFFSET: usize = 2;
+const RECORD_LEVEL_OFFSET: usize = 6;
+const CHECKSUM_OFFSET: usize = 14;
+
+const MAGIC: u8 = b'S';
+const VERSION: u8 = 1;
+
+/// The name of the fw_cfg file containing settings passed by the host with `add_uefi_boot`'s
+/// `--fw-cfg settings` option.
+pub const HOST_SETTINGS_FILE: &str = "opt/simpleos/settings";
+
+/// A single instance of the RTC's CMOS memory, protected against multiple accesses by a
+/// spinlock-based `Mutex`, as each access is made through two ports.
+static CMOS: Mutex<Cmos> = Mutex::new(Cmos::new());
+
+/// The kernel's settings.
+#[derive(Clone, Copy)]
+pub struct Settings {
+    /// The number of times the kernel has booted since the settings were created.
+    pub boot_count: u32,
+    /// The least severe level of log record that is emitted.
+    pub record_level: log_record::Level,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            boot_count: 0,
+            record_level: log_record::Level::Debug,
+        }
+    }
+}
+
+impl Settings {
+    /// Changes the settings named in `text`, which contains a "NAME=VALUE" line for each setting
+    /// changed. The only name is "record_level", whose value is the name of a log record level,
+    /// e.g., "info". Blank lines are ignored. Returns an error if any line can't be applied, in
+    /// which case the lines before it have been.
+    pub fn apply(&mut self, text: &str) -> Result<(), &'static str> {
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (name, value) = line
+                .split_once('=')
+                .ok_or("settings must be in the form NAME=VALUE")?;
+
+            match name.trim() {
+                "record_level" => {
+                    self.record_level =
+                        log_record::Level::from_name(value.trim()).ok_or("unknown record level")?;
+                }
+                _ => return Err("unknown setting"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the settings in `bytes`, or `None` if they don't have the expected magic number,
+    /// version and checksum.
+    fn from_bytes(bytes: &[u8; SETTINGS_LEN]) -> Option<Settings> {
+        let expected_checksum =
+            u16::from_le_bytes([bytes[CHECKSUM_OFFSET], bytes[CHECKSUM_OFFSET + 1]]);
+        if bytes[MAGIC_OFFSET] != MAGIC
+            || bytes[VERSION_OFFSET] != VERSION
+            || checksum(&bytes[..CHECKSUM_OFFSET]) != expected_checksum
+        {
+            return None;
+        }
+
+        let mut boot_count = [0; 4];
+        boot_count.copy_from_slice(&bytes[BOOT_COUNT_OFFSET..BOOT_COUNT_OFFSET + 4]);
+
+        Some(Settings {
+            boot_count: u32::from_le_bytes(boot_count),
+            record_level: *log_record::Level::ALL.get(bytes[RECORD_LEVEL_OFFSET] as usize)?,
+        })
+    }
+
+    /// Returns the settings in the form stored in CMOS memory.
+    fn to_bytes(self) -> [u8; SETTINGS_LEN] {
+        let mut bytes = [0; SETTINGS_LEN];
+        bytes[MAGIC_OFFSET] = MAGIC;
+        bytes[VERSION_OFFSET] = VERSION;
+        bytes[BOOT_COUNT_OFFSET..BOOT_COUNT_OFFSET + 4]
+            .copy_from_slice(&self.boot_count.to_le_bytes());
+        bytes[RECORD_LEVEL_OFFSET] = self.record_level as u8;
+
+        let checksum = checksum(&bytes[..CHECKSUM_OFFSET]);
+        bytes[CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+}
+
+/// Reads the settings from CMOS memory. Returns `None` if the memory doesn't contain valid
+/// settings, e.g., because they have never been stored.
+pub fn load() -> Option<Settings> {
+    let mut bytes = [0; SETTINGS_LEN];
+    let mut cmos = CMOS.lock();
+    for (index, b) in (SETTINGS_START..).zip(bytes.iter_mut()) {
+        *b = cmos.read(index);
+    }
+
+    let settings = Settings::from_bytes(&bytes);
+    trace_event!(Category::Settings, "Loaded: valid {}", settings.is_some());
+    settings
+}
+
+/// Writes `settings` to CMOS memory.
+pub fn store(settings: &Settings) {
+    let mut cmos = CMOS.lock();
+    for (index, b) in (SETTINGS_START..).zip(settings.to_bytes()) {
+        cmos.write(index, b);
+    }
+
+    trace_event!(
+        Category::Settings,
+        "Stored: boot count {}",
+        settings.boot_count
+    );
+}
+
+/// Returns the sum of `bytes`.
+fn checksum(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .fold(0, |sum: u16, &b| sum.wrapping_add(u16::from(b)))
+}
+
+struct Cmos {
+    index: Port<u8, WriteOnlyAccess>,
+    data: Port<u8, ReadWriteAccess>,
+}
+
+impl Cmos {
+    const fn new() -> Self {
+        Self {
+            index: CMOS_PORTS.port(INDEX_OFFSET),
+            data: CMOS_PORTS.port(DATA_OFFSET),
+        }
+    }
+
+    /// Returns the byte of CMOS memory at `index`.
+    fn read(&mut self, index: u8) -> u8 {
+        self.index.write(index);
+        self.data.read()
+    }
+
+    /// Writes `value` to the byte of CMOS memory at `index`.
+    fn write(&mut self, index: u8, value: u8) {
+        self.index.write(index);
+        self.data.write(value);
+    }
+}
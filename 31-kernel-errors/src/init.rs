@@ -0,0 +1,129 @@
+//! Runs the kernel's initialization functions in order.
+//!
+//! Each subsystem registers its initialization function with `init_call!`, next to the function
+//! itself, rather than `simpleos_main()` calling each by hand. The macro places a descriptor of
+//! the function in the `simpleos_init` linker section. The linker gathers every descriptor into
+//! this one section, and defines the `__start_simpleos_init` and `__stop_simpleos_init` symbols at
+//! its start and end, so `run_all()` can walk them as an array.
+//!
+//! Functions are run in order of their `Level`. The order of functions with the same level is
+//! chosen by the linker, so a function that depends on another must have a later level. Each
+//! function is timed as a stage of boot, and if it returns an error, the error is output and the
+//! remaining functions are still run, unless the function was registered as `required`, in which
+//! case the kernel panics, as the rest of the kernel can't run without it. Each function's success
+//! or failure is emitted as a log record.
+
+use crate::bootinfo::BootInfo;
+use crate::trace::Category;
+use crate::{boot_timing, log_record, trace_event};
+use core::ptr::addr_of;
+use simpleos_kernel::eprintln;
+use simpleos_kernel::error::KernelError;
+
+/// When an initialization function runs, relative to the others. Levels run in the order they are
+/// declared here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    /// Checks that the rest of the kernel relies on, such as the bootloader's configuration.
+    Early,
+    /// Functions that test or set up memory.
+    Memory,
+    /// Functions that detect the platform, such as the hypervisor and its clock.
+    Platform,
+    /// Device drivers.
+    Device,
+    /// Services built on devices, such as the random number generator.
+    Service,
+}
+
+impl Level {
+    const ALL: [Level; 5] = [
+        Level::Early,
+        Level::Memory,
+        Level::Platform,
+        Level::Device,
+        Level::Service,
+    ];
+}
+
+/// Describes an initialization function registered with `init_call!`.
+pub struct InitCall {
+    pub name: &'static str,
+    pub level: Level,
+    pub init: fn(&BootInfo) -> Result<(), KernelError>,
+    pub required: bool,
+}
+
+// Only the addresses of these symbols are used, so they are declared with a type that is safe to
+// use in an `extern` block.
+extern "C" {
+    static __start_simpleos_init: u8;
+    static __stop_simpleos_init: u8;
+}
+
+/// Registers `$init`, a function taking a `&BootInfo` and returning `Result<(), KernelError>`, to
+/// be run at `$level` under the name `$name`, which is also the name of its boot stage. Adding
+/// `required` after the function makes the kernel panic if it fails.
+#[macro_export]
+macro_rules! init_call {
+    (@register $level:expr, $name:literal, $init:path, $required:literal) => {
+        const _: () = {
+            // The descriptor is only referenced through the section's start and end symbols, so
+            // `#[used]` stops the compiler discarding it.
+            #[used]
+            #[link_section = "simpleos_init"]
+            static INIT_CALL: $crate::init::InitCall = $crate::init::InitCall {
+                name: $name,
+                level: $level,
+                init: $init,
+                required: $required,
+            };
+        };
+    };
+    ($level:expr, $name:literal, $init:path) => {
+        $crate::init_call!(@register $level, $name, $init, false);
+    };
+    ($level:expr, $name:literal, $init:path, required) => {
+        $crate::init_call!(@register $level, $name, $init, true);
+    };
+}
+
+/// Runs every registered initialization function in order of level, each as a boot stage. Errors
+/// are output, and don't stop later functions running unless the function that failed is required.
+///
+/// Panics if a required function fails.
+pub fn run_all(boot_info: &BootInfo) {
+    for level in Level::ALL {
+        for call in init_calls().iter().filter(|call| call.level == level) {
+            trace_event!(Category::Boot, "Init {:?} {}", level, call.name);
+
+            match boot_timing::stage(call.name, || (call.init)(boot_info)) {
+                Ok(()) => log_record!(log_record::Level::Info, "init", "{} done", call.name),
+                Err(e) => {
+                    trace_event!(Category::Boot, "Init {} failed", call.name);
+                    log_record!(
+                        log_record::Level::Error,
+                        "init",
+                        "{} failed: {e}",
+                        call.name
+                    );
+                    if call.required {
+                        panic!("Initialization of {} failed: {e}", call.name);
+                    }
+                    eprintln!("Initialization of {} failed: {e}", call.name);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the descriptors in the `simpleos_init` section.
+fn init_calls() -> &'static [InitCall] {
+    // The linker places the start and end symbols around the section, which only contains
+    // `InitCall` statics, so the memory between them is an array of them.
+    unsafe {
+        let start = addr_of!(__start_simpleos_init).cast::<InitCall>();
+        let end = addr_of!(__stop_simpleos_init).cast::<InitCall>();
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    }
+}
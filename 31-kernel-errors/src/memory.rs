@@ -0,0 +1,14 @@
+//! Helpers for working with the kernel's virtual and physical address spaces.
+
+use crate::boot_config::PHYSICAL_MEMORY_OFFSET;
+use simpleos_kernel::arch;
+
+/// Returns the physical address that the given virtual address is mapped to by the active page
+/// tables, or `None` if the address is not mapped.
+///
+/// On x86-64, the page tables are walked through the mapping of physical memory that the
+/// bootloader creates at `PHYSICAL_MEMORY_OFFSET`, so this relies on
+/// `boot_config::log_and_validate()` having confirmed that the mapping exists.
+pub fn translate_addr(addr: u64) -> Option<u64> {
+    unsafe { arch::translate_addr(addr, PHYSICAL_MEMORY_OFFSET) }
+}
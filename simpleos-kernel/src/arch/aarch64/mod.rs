@@ -0,0 +1,61 @@
+//! Code specific to the AArch64 architecture.
+//!
+//! This only provides what the architecture-independent parts of the library need, so that they
+//! can be built for AArch64. Booting the kernel on AArch64 also requires a console, as AArch64 has
+//! no I/O ports for the existing ones to use.
+
+use core::arch::asm;
+
+/// The bits of MPIDR_EL1 containing affinity level 0, which identifies a CPU within its cluster.
+const MPIDR_AFF0_MASK: u64 = 0xFF;
+
+// The bit of PAR_EL1 set if an address translation failed, the bits containing the physical
+// address of the page if it succeeded, and the bits of a virtual address within a page.
+const PAR_FAULT: u64 = 1;
+const PAR_PA_MASK: u64 = 0x0000_FFFF_FFFF_F000;
+const PAGE_OFFSET_MASK: u64 = 0xFFF;
+
+/// Returns the generic timer's virtual count, which increases at a constant rate.
+pub fn timestamp() -> u64 {
+    let count: u64;
+    unsafe {
+        asm!("mrs {}, cntvct_el0", out(reg) count, options(nomem, nostack, preserves_flags));
+    }
+    count
+}
+
+/// Returns affinity level 0 of the CPU executing this function, which is unique on systems with a
+/// single cluster of CPUs.
+pub fn current_cpu() -> u32 {
+    let mpidr: u64;
+    unsafe {
+        asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nomem, nostack, preserves_flags));
+    }
+    (mpidr & MPIDR_AFF0_MASK) as u32
+}
+
+/// Returns the physical address that virtual address `addr` is mapped to by the active translation
+/// tables, or `None` if it is not mapped. The CPU translates the address itself with the AT
+/// instruction, so `physical_memory_offset` isn't used.
+///
+/// # Safety
+///
+/// This is always safe on AArch64, but is `unsafe` to match the x86-64 version.
+pub unsafe fn translate_addr(addr: u64, _physical_memory_offset: u64) -> Option<u64> {
+    let par: u64;
+    unsafe {
+        asm!(
+            "at s1e1r, {addr}",
+            "isb",
+            "mrs {par}, par_el1",
+            addr = in(reg) addr,
+            par = out(reg) par,
+            options(nostack, preserves_flags),
+        );
+    }
+
+    if par & PAR_FAULT != 0 {
+        return None;
+    }
+    Some(par & PAR_PA_MASK | addr & PAGE_OFFSET_MASK)
+}
@@ -0,0 +1,25 @@
+//! Code specific to the CPU architecture.
+//!
+//! Each supported architecture has a submodule, and the one for the architecture being built for
+//! is re-exported here, so the rest of the library uses, e.g., `arch::timestamp()` rather than an
+//! x86-64 instruction, and can be ported to another architecture by adding a submodule. Each
+//! submodule provides:
+//!
+//! - `timestamp()`, which returns a counter that increases at a constant rate;
+//! - `current_cpu()`, which returns a number identifying the CPU executing it;
+//! - `translate_addr()`, which returns the physical address a virtual address is mapped to.
+//!
+//! Only x86-64 has I/O ports and model-specific registers, so `portio` and `write_msr()` are only
+//! available on x86-64, as are the console and serial port modules that use ports. The `aarch64`
+//! submodule is a skeleton that provides the functions above, but the kernel doesn't yet boot on
+//! AArch64.
+
+#[cfg(target_arch = "aarch64")]
+pub mod aarch64;
+#[cfg(target_arch = "x86_64")]
+pub mod x86_64;
+
+#[cfg(target_arch = "aarch64")]
+pub use self::aarch64::*;
+#[cfg(target_arch = "x86_64")]
+pub use self::x86_64::*;
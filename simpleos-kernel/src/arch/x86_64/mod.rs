@@ -0,0 +1,54 @@
+//! Code specific to the x86-64 architecture.
+
+use core::arch::x86_64::{__cpuid, _rdtsc};
+use x86_64::registers::control::Cr3;
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::paging::{OffsetPageTable, PageTable, Translate};
+use x86_64::VirtAddr;
+
+pub mod portio;
+
+// CPUID leaf and bit position of the initial APIC ID, which identifies the CPU executing CPUID.
+const CPUID_FEATURES_LEAF: u32 = 1;
+const CPUID_FEATURES_EBX_APIC_ID_SHIFT: u32 = 24;
+
+/// Returns the time stamp counter, which increases at a constant rate on current CPUs.
+pub fn timestamp() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// Returns the initial APIC ID of the CPU executing this function.
+pub fn current_cpu() -> u32 {
+    __cpuid(CPUID_FEATURES_LEAF).ebx >> CPUID_FEATURES_EBX_APIC_ID_SHIFT
+}
+
+/// Writes `value` to the model-specific register `msr`.
+///
+/// # Safety
+///
+/// Writing an MSR can change how the CPU behaves, or make the hypervisor write to memory, so the
+/// caller must ensure that writing `value` to `msr` doesn't break memory safety.
+pub unsafe fn write_msr(msr: u32, value: u64) {
+    unsafe { Msr::new(msr).write(value) }
+}
+
+/// Returns the physical address that virtual address `addr` is mapped to by the active page
+/// tables, or `None` if it is not mapped.
+///
+/// # Safety
+///
+/// All of physical memory must be mapped at virtual address `physical_memory_offset`, as the page
+/// tables are read through this mapping.
+pub unsafe fn translate_addr(addr: u64, physical_memory_offset: u64) -> Option<u64> {
+    let (level_4_frame, _) = Cr3::read();
+    let offset = VirtAddr::new(physical_memory_offset);
+    let level_4_table_ptr: *mut PageTable =
+        (offset + level_4_frame.start_address().as_u64()).as_mut_ptr();
+
+    // The caller guarantees that `level_4_table_ptr` points to the active level 4 page table. The
+    // table is only read, and nothing else modifies page tables while this function runs.
+    let page_table = unsafe { OffsetPageTable::new(&mut *level_4_table_ptr, offset) };
+    page_table
+        .translate_addr(VirtAddr::try_new(addr).ok()?)
+        .map(|addr| addr.as_u64())
+}
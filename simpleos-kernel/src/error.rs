@@ -0,0 +1,66 @@
+//! The error type returned by the kernel's initialization functions and drivers.
+//!
+//! Each variant is a category of failure, which callers can act on, with a description of what
+//! failed, which is output when the error is displayed, e.g., `println!("{e}")` outputs "not found:
+//! QEMU fw_cfg interface". The enum is `#[non_exhaustive]`, so categories can be added as drivers
+//! need them without breaking code that matches on the existing ones.
+
+use core::fmt::{self, Display};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KernelError {
+    /// Memory could not be allocated, or was not mapped where it was needed.
+    NoMemory(&'static str),
+    /// A device, file or other resource does not exist.
+    NotFound(&'static str),
+    /// A device reported an error, or did not behave as expected.
+    DeviceError(&'static str),
+    /// A value passed to the kernel, e.g., by the bootloader or the host, is not valid.
+    InvalidArgument(&'static str),
+}
+
+impl KernelError {
+    /// Returns the description of what failed.
+    pub fn description(&self) -> &'static str {
+        match self {
+            KernelError::NoMemory(description)
+            | KernelError::NotFound(description)
+            | KernelError::DeviceError(description)
+            | KernelError::InvalidArgument(description) => description,
+        }
+    }
+
+    /// Returns the name of the error's category.
+    fn category_name(&self) -> &'static str {
+        match self {
+            KernelError::NoMemory(_) => "out of memory",
+            KernelError::NotFound(_) => "not found",
+            KernelError::DeviceError(_) => "device error",
+            KernelError::InvalidArgument(_) => "invalid argument",
+        }
+    }
+}
+
+impl Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.category_name(), self.description())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_includes_category_and_description() {
+        assert_eq!(
+            KernelError::NotFound("QEMU fw_cfg interface").to_string(),
+            "not found: QEMU fw_cfg interface"
+        );
+        assert_eq!(
+            KernelError::DeviceError("fw_cfg DMA transfer failed").to_string(),
+            "device error: fw_cfg DMA transfer failed"
+        );
+    }
+}
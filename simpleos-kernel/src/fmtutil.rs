@@ -0,0 +1,215 @@
+//! Types that format values in human-readable ways, for use with `print!` and similar macros.
+//!
+//! Each type wraps the value to be formatted and implements `Display`, e.g.,
+//! `println!("{}", ByteSize(4096))` outputs "4.0 KiB". Width and alignment are supported by
+//! `ByteSize` and `HumanDuration`, e.g., `{:>10}`.
+
+use core::fmt::{self, Display, Write};
+use core::time::Duration;
+
+/// The number of bytes shown on each line of a `HexDump`.
+const HEX_DUMP_BYTES_PER_LINE: usize = 16;
+
+/// The maximum length of the text produced by `ByteSize` and `HumanDuration`, before padding.
+const MAX_VALUE_LEN: usize = 32;
+
+/// Displays a byte slice in the same format as `hexdump -C`, i.e., one line per 16 bytes, each
+/// containing the offset of the first byte, the bytes in hexadecimal, and the bytes as ASCII with
+/// unprintable characters shown as '.'. Each line ends with a newline.
+pub struct HexDump<'a>(pub &'a [u8]);
+
+impl Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (line, bytes) in self.0.chunks(HEX_DUMP_BYTES_PER_LINE).enumerate() {
+            write!(f, "{:08x} ", line * HEX_DUMP_BYTES_PER_LINE)?;
+
+            for i in 0..HEX_DUMP_BYTES_PER_LINE {
+                // An extra space separates the two halves of the line.
+                if i == HEX_DUMP_BYTES_PER_LINE / 2 {
+                    f.write_char(' ')?;
+                }
+
+                match bytes.get(i) {
+                    Some(b) => write!(f, " {b:02x}")?,
+                    None => f.write_str("   ")?,
+                }
+            }
+
+            f.write_str("  |")?;
+            for &b in bytes {
+                let c = if b.is_ascii_graphic() || b == b' ' {
+                    char::from(b)
+                } else {
+                    '.'
+                };
+                f.write_char(c)?;
+            }
+            f.write_str("|\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Displays a number of bytes using the largest binary unit in which it is at least 1, with one
+/// decimal place, e.g., "512 B", "1.5 KiB" or "119.0 MiB".
+pub struct ByteSize(pub u64);
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+        if self.0 < 1024 {
+            return pad(f, format_args!("{} B", self.0));
+        }
+
+        let mut unit = 0;
+        while unit + 1 < UNITS.len() && self.0 >> (10 * (unit + 2)) != 0 {
+            unit += 1;
+        }
+
+        // Calculated in tenths of the unit, rounding down, to avoid floating point.
+        let tenths = (u128::from(self.0) * 10) >> (10 * (unit + 1));
+        pad(
+            f,
+            format_args!("{}.{} {}", tenths / 10, tenths % 10, UNITS[unit]),
+        )
+    }
+}
+
+/// Displays a duration using the largest unit in which it is at least 1, with one decimal place
+/// for units other than nanoseconds, e.g., "750 ns", "12.5 µs" or "1.2 s".
+pub struct HumanDuration(pub Duration);
+
+impl Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [(u128, &str); 3] = [(1_000_000_000, "s"), (1_000_000, "ms"), (1_000, "µs")];
+
+        let nanos = self.0.as_nanos();
+        for (nanos_per_unit, unit) in UNITS {
+            if nanos >= nanos_per_unit {
+                let tenths = nanos * 10 / nanos_per_unit;
+                return pad(f, format_args!("{}.{} {unit}", tenths / 10, tenths % 10));
+            }
+        }
+
+        pad(f, format_args!("{nanos} ns"))
+    }
+}
+
+/// Formats `args` into a buffer, then writes it to `f`, applying the width and alignment that
+/// `f` specifies. `Formatter::pad()` needs the complete text to do this.
+fn pad(f: &mut fmt::Formatter, args: fmt::Arguments) -> fmt::Result {
+    let mut buf = ValueBuffer {
+        bytes: [0; MAX_VALUE_LEN],
+        len: 0,
+    };
+    buf.write_fmt(args)?;
+
+    // Only whole strings are written to the buffer, so it always holds valid UTF-8.
+    f.pad(core::str::from_utf8(&buf.bytes[..buf.len]).map_err(|_| fmt::Error)?)
+}
+
+struct ValueBuffer {
+    bytes: [u8; MAX_VALUE_LEN],
+    len: usize,
+}
+
+impl Write for ValueBuffer {
+    /// Appends `s` to the buffer. Returns an error if the buffer is too small to hold it.
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        let end = self.len + s.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_dump_full_line() {
+        let bytes: [u8; 16] = *b"0123456789abcdef";
+        assert_eq!(
+            HexDump(&bytes).to_string(),
+            "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n"
+        );
+    }
+
+    #[test]
+    fn hex_dump_partial_line_and_unprintable_bytes() {
+        let bytes: [u8; 20] = *b"Hello, world!\n\0\xffxyz ";
+        assert_eq!(
+            HexDump(&bytes).to_string(),
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|\n\
+             00000010  78 79 7a 20                                       |xyz |\n"
+        );
+    }
+
+    #[test]
+    fn hex_dump_empty() {
+        assert_eq!(HexDump(&[]).to_string(), "");
+    }
+
+    #[test]
+    fn byte_size_units() {
+        assert_eq!(ByteSize(0).to_string(), "0 B");
+        assert_eq!(ByteSize(1023).to_string(), "1023 B");
+        assert_eq!(ByteSize(1024).to_string(), "1.0 KiB");
+        assert_eq!(ByteSize(1536).to_string(), "1.5 KiB");
+        assert_eq!(ByteSize(1024 * 1024 - 1).to_string(), "1023.9 KiB");
+        assert_eq!(ByteSize(119 * 1024 * 1024).to_string(), "119.0 MiB");
+        assert_eq!(ByteSize(u64::MAX).to_string(), "15.9 EiB");
+    }
+
+    #[test]
+    fn byte_size_padding() {
+        assert_eq!(format!("{:>10}", ByteSize(4096)), "   4.0 KiB");
+        assert_eq!(format!("{:<8}|", ByteSize(12)), "12 B    |");
+    }
+
+    #[test]
+    fn human_duration_units() {
+        assert_eq!(HumanDuration(Duration::ZERO).to_string(), "0 ns");
+        assert_eq!(
+            HumanDuration(Duration::from_nanos(750)).to_string(),
+            "750 ns"
+        );
+        assert_eq!(
+            HumanDuration(Duration::from_nanos(12_500)).to_string(),
+            "12.5 µs"
+        );
+        assert_eq!(
+            HumanDuration(Duration::from_millis(999)).to_string(),
+            "999.0 ms"
+        );
+        assert_eq!(
+            HumanDuration(Duration::from_millis(1_250)).to_string(),
+            "1.2 s"
+        );
+    }
+
+    #[test]
+    fn human_duration_padding() {
+        assert_eq!(
+            format!("{:>8}", HumanDuration(Duration::from_nanos(5))),
+            "    5 ns"
+        );
+    }
+
+    #[test]
+    fn value_buffer_rejects_overflow() {
+        let mut buf = ValueBuffer {
+            bytes: [0; MAX_VALUE_LEN],
+            len: 0,
+        };
+        assert!(buf.write_str(&"x".repeat(MAX_VALUE_LEN)).is_ok());
+        assert!(buf.write_str("y").is_err());
+        assert_eq!(buf.len, MAX_VALUE_LEN);
+    }
+}
@@ -0,0 +1,35 @@
+#![cfg_attr(not(test), no_std)] // Links Rust's standard library only when testing on the host.
+
+//! Code shared by the phases of simpleos, from phase 17 onwards.
+//!
+//! Each phase is a separate kernel binary, and earlier phases contain their own copies of this
+//! code as it was when the phase was written. Later phases depend on this library instead, so a
+//! fix made here applies to all of them. It provides:
+//!
+//! - `print!`, `println!`, `eprint!` and `eprintln!` macros, which send output to QEMU's debugging
+//!   console and the serial port COM1, or only to COM1 if the console is not present.
+//! - Code specific to the CPU architecture, in `arch`, including timestamps, address translation,
+//!   MSR writes and typed handles for I/O ports in `arch::portio`.
+//! - A spinlock-based `Mutex` that can detect deadlocks, in `sync`.
+//! - Types that format values in human-readable ways, in `fmtutil`.
+//! - `KernelError`, the error type returned by initialization functions and drivers, in `error`.
+//! - The kernel's panic handler.
+//!
+//! The library can be built for the host as well as for the kernel's target, so that code which
+//! doesn't depend on the hardware can be tested with `cargo test`. The panic handler is only
+//! included when building for the kernel's target, which has no operating system, and the modules
+//! that access x86-64 devices are only included when building for x86-64, so the tests also run
+//! on hosts with other architectures. The "lock-diagnostics" feature requires an architecture with
+//! an `arch` submodule, which are x86-64 and AArch64.
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub mod arch;
+pub mod error;
+pub mod fmtutil;
+#[cfg(all(target_os = "none", target_arch = "x86_64"))]
+mod panic;
+#[cfg(target_arch = "x86_64")]
+pub mod qemu_console;
+#[cfg(target_arch = "x86_64")]
+pub mod serial;
+pub mod sync;
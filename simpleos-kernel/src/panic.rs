@@ -0,0 +1,33 @@
+//! The kernel's panic handler.
+
+use crate::{qemu_console, serial};
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+/// Rust requires a function with the "panic_handler" attribute [1] to be defined. This is usually
+/// called if a panic occurs, except that this is overridden by the `panic = "abort"` lines in the
+/// Cargo.toml of each phase to keep things simple. The function name is arbirary as only the
+/// attribute is used to identify which function should be called.
+///
+/// This function prints a message indicating that the kernel has panicked and the debug output
+/// of the `PanicInfo` object passed, which includes the panic message and the line of code where
+/// the panic occurred. The message is sent to both QEMU's debugging console and COM1.
+///
+/// [1]: https://doc.rust-lang.org/reference/runtime.html#the-panic_handler-attribute
+#[panic_handler]
+fn panic(panic_info: &PanicInfo) -> ! {
+    // The panic may have occurred while the console was locked, e.g., if a deadlock involving it
+    // was detected. The code holding the lock will never run again, so it is safe to bypass it.
+    unsafe {
+        qemu_console::emergency_write(format_args!("\nKERNEL PANIC\n{panic_info:#?}\n"));
+    }
+
+    // Also send the message to COM1 with other errors, unless the panic occurred while it was
+    // locked.
+    if let Some(mut serial) = serial::SERIAL.try_lock() {
+        writeln!(serial, "KERNEL PANIC\n{panic_info:#?}").unwrap();
+    }
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
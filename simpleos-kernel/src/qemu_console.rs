@@ -0,0 +1,225 @@
+//! Defines `print!` and `println!` macros to send data to QEMU's debugging console, and `eprint!`
+//! and `eprintln!` macros to send error messages.
+//!
+//! Error messages are sent to the debugging console with `ERROR_PREFIX` added, so they appear in
+//! order with the rest of the output but stand out from it. They are also sent to the serial port
+//! COM1, which `add_uefi_boot` can direct to a different file on the host, so that errors can be
+//! separated from the rest of the output.
+//!
+//! Output is collected in a line buffer, and only written to the console's port when a newline is
+//! output, the buffer is full, or `flush()` is called. Each call to `print!` holds the buffer's
+//! lock while its output is formatted, so output from different parts of the kernel is never
+//! interleaved within a call. The panic handler uses `emergency_write()` instead, which bypasses
+//! both the buffer and its lock.
+//!
+//! The debugging console only exists in QEMU and Bochs, so writing to its port on real hardware
+//! may affect whatever device uses the port instead. `probe()` checks whether the console is
+//! present, and if it isn't, all output is sent to COM1 instead. Until `probe()` is called, the
+//! console is assumed to be present.
+
+use crate::arch::portio::{LockedPort, Port};
+use crate::serial::SERIAL;
+use crate::sync::Mutex;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The size of the line buffer. Longer lines are written in pieces of this size.
+const LINE_BUFFER_SIZE: usize = 256;
+
+/// The text added to the start of each error message.
+const ERROR_PREFIX: &str = "ERROR: ";
+
+/// The value read from the console's port when the console is present.
+const CONSOLE_PRESENT_VALUE: u8 = 0xE9;
+
+/// Whether the debugging console is present, which is assumed until `probe()` is called.
+static PRESENT: AtomicBool = AtomicBool::new(true);
+
+// A single instance of the QEMU debugging console port, protected against multiple accesses by a
+// spinlock-based `Mutex`. Accesses aren't traced, as trace output is itself written to this port.
+pub static QEMU_CONSOLE_PORT: LockedPort<u8> = LockedPort::new(unsafe { Port::new_untraced(0xE9) });
+
+// A single instance of the line buffer, protected against multiple accesses by a spinlock-based
+// `Mutex`. This lock is always acquired before the port's.
+static LINE_BUFFER: Mutex<LineBuffer> = Mutex::new(LineBuffer {
+    bytes: [0; LINE_BUFFER_SIZE],
+    len: 0,
+});
+
+struct LineBuffer {
+    bytes: [u8; LINE_BUFFER_SIZE],
+    len: usize,
+}
+
+impl LineBuffer {
+    /// Writes the contents of the buffer to QEMU's debugging console, or to COM1 if the console is
+    /// not present, and empties the buffer.
+    fn flush(&mut self) {
+        write_bytes(&self.bytes[..self.len]);
+        self.len = 0;
+    }
+}
+
+impl Write for LineBuffer {
+    /// Adds the given string to the buffer, flushing the buffer after each newline and whenever it
+    /// is full. This function is always successful so never returns an error.
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        for b in s.bytes() {
+            self.bytes[self.len] = b;
+            self.len += 1;
+
+            if b == b'\n' || self.len == LINE_BUFFER_SIZE {
+                self.flush();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Outputs the given bytes to QEMU's debug console on the host. To see the output, the
+/// "-debugcon" argument must be passed to QEMU when it is invoked. If the console is not present,
+/// the bytes are sent to COM1 instead.
+fn write_bytes(bytes: &[u8]) {
+    if !present() {
+        let mut serial = SERIAL.lock();
+        for &b in bytes {
+            serial.write_byte(b);
+        }
+        return;
+    }
+
+    for &b in bytes {
+        QEMU_CONSOLE_PORT.write(b);
+    }
+}
+
+/// Checks whether QEMU's debugging console is present by reading its port, which returns 0xE9 in
+/// QEMU and Bochs. Returns `true` if it is present. If it isn't, all further output is sent to
+/// COM1, so `Serial::init()` should be called first.
+pub fn probe() -> bool {
+    let present = QEMU_CONSOLE_PORT.read() == CONSOLE_PRESENT_VALUE;
+    PRESENT.store(present, Ordering::Relaxed);
+    present
+}
+
+/// Returns `false` if `probe()` found that QEMU's debugging console is not present, in which case
+/// output is sent to COM1 instead.
+pub fn present() -> bool {
+    PRESENT.load(Ordering::Relaxed)
+}
+
+/// Writes data to QEMU's debugging console. The passed data is of type `core::fmt::Arguments`
+/// because this is the type: returned from the `format_args!` macro; and required by the `Write`
+/// traits `write_fmt()` method.
+///
+/// This function is intended only for internal use, but is declared `pub` to allow its use from
+/// macros.
+//
+// The implementation is closely based on <https://os.phil-opp.com/testing/#serial-port>.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    LINE_BUFFER.lock().write_fmt(args).unwrap();
+}
+
+/// Writes an error message to QEMU's debugging console with `ERROR_PREFIX` added, and to COM1.
+/// If the console is not present, the message is only written to COM1, once.
+///
+/// This function is intended only for internal use, but is declared `pub` to allow its use from
+/// macros.
+#[doc(hidden)]
+pub fn _eprint(args: fmt::Arguments) {
+    let message = format_args!("{ERROR_PREFIX}{args}");
+    LINE_BUFFER.lock().write_fmt(message).unwrap();
+    if present() {
+        SERIAL.lock().write_fmt(message).unwrap();
+    }
+}
+
+/// Writes any output that is waiting in the line buffer, i.e., output since the last newline.
+pub fn flush() {
+    LINE_BUFFER.lock().flush();
+}
+
+/// Writes data directly to QEMU's debugging console, bypassing the line buffer. This is intended
+/// for the panic handler, and works even if the panic occurred while the buffer or the port was
+/// locked. Any output waiting in the buffer is written first if the buffer isn't locked. If the
+/// console is not present, only the waiting output is written, to COM1 and only if neither it nor
+/// the buffer is locked, as the panic handler writes to COM1 itself.
+///
+/// # Safety
+///
+/// The code holding the console port's lock, if any, must never run again, as the lock is
+/// released regardless.
+pub unsafe fn emergency_write(args: fmt::Arguments) {
+    if !present() {
+        if let (Some(mut line_buffer), Some(mut serial)) =
+            (LINE_BUFFER.try_lock(), SERIAL.try_lock())
+        {
+            for &b in &line_buffer.bytes[..line_buffer.len] {
+                serial.write_byte(b);
+            }
+            line_buffer.len = 0;
+        }
+        return;
+    }
+
+    unsafe {
+        QEMU_CONSOLE_PORT.force_unlock();
+    }
+
+    if let Some(mut line_buffer) = LINE_BUFFER.try_lock() {
+        line_buffer.flush();
+    }
+
+    EmergencyWriter {}.write_fmt(args).unwrap();
+}
+
+struct EmergencyWriter {}
+
+impl Write for EmergencyWriter {
+    /// Outputs the given string to QEMU's debug console without buffering. This function is always
+    /// successful so never returns an error.
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// An alternate implementation of the standard `print!` macro, except that output is sent to QEMU's
+/// debugging console.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {{
+        $crate::qemu_console::_print(format_args!($($arg)*));
+    }};
+}
+
+/// An alternate implementation of the standard `println!` macro, except that output is sent to
+/// QEMU's debugging console.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => {{
+        $crate::print!("{}\n", format_args!($($arg)*));
+    }};
+}
+
+/// An alternate implementation of the standard `eprint!` macro, except that output is sent to
+/// QEMU's debugging console, with `ERROR_PREFIX` added, and to the serial port COM1.
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => {{
+        $crate::qemu_console::_eprint(format_args!($($arg)*));
+    }};
+}
+
+/// An alternate implementation of the standard `eprintln!` macro, except that output is sent to
+/// QEMU's debugging console, with `ERROR_PREFIX` added, and to the serial port COM1.
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => {{
+        $crate::eprint!("{}\n", format_args!($($arg)*));
+    }};
+}
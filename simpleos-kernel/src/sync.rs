@@ -0,0 +1,151 @@
+//! A spinlock-based `Mutex` that can detect deadlocks.
+//!
+//! This wraps `spin::Mutex` and is used in its place throughout the kernel. If the kernel is built
+//! with the "lock-diagnostics" feature, each lock records where it was last acquired and by which
+//! CPU, and a call to `lock()` that spins for longer than `SPIN_LIMIT_CYCLES` panics with both the
+//! location that holds the lock and the location waiting for it. Without a check like this, a
+//! deadlock just causes the kernel to hang silently. Without the feature, `lock()` is identical to
+//! `spin::Mutex::lock()`.
+
+pub use spin::MutexGuard;
+
+#[cfg(feature = "lock-diagnostics")]
+use crate::arch;
+#[cfg(feature = "lock-diagnostics")]
+use core::fmt::Display;
+#[cfg(feature = "lock-diagnostics")]
+use core::hint::spin_loop;
+#[cfg(feature = "lock-diagnostics")]
+use core::panic::Location;
+#[cfg(feature = "lock-diagnostics")]
+use core::ptr;
+#[cfg(feature = "lock-diagnostics")]
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+
+/// The number of `arch::timestamp()` ticks `lock()` spins for before assuming a deadlock. On
+/// x86-64 this is a few seconds on current CPUs, which is far longer than any lock in the kernel is
+/// held for.
+#[cfg(feature = "lock-diagnostics")]
+const SPIN_LIMIT_CYCLES: u64 = 1 << 33;
+
+pub struct Mutex<T> {
+    inner: spin::Mutex<T>,
+    #[cfg(feature = "lock-diagnostics")]
+    holder_location: AtomicPtr<Location<'static>>,
+    #[cfg(feature = "lock-diagnostics")]
+    holder_cpu: AtomicU32,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: spin::Mutex::new(value),
+            #[cfg(feature = "lock-diagnostics")]
+            holder_location: AtomicPtr::new(ptr::null_mut()),
+            #[cfg(feature = "lock-diagnostics")]
+            holder_cpu: AtomicU32::new(0),
+        }
+    }
+
+    /// Acquires the lock, spinning until it is available.
+    #[cfg(not(feature = "lock-diagnostics"))]
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.inner.lock()
+    }
+
+    /// Acquires the lock, spinning until it is available, and records the caller's location and
+    /// CPU.
+    ///
+    /// Panics if the lock is not acquired within `SPIN_LIMIT_CYCLES`.
+    #[cfg(feature = "lock-diagnostics")]
+    #[track_caller]
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let start = arch::timestamp();
+
+        let guard = loop {
+            if let Some(guard) = self.inner.try_lock() {
+                break guard;
+            }
+
+            let spun = arch::timestamp().wrapping_sub(start);
+            if spun > SPIN_LIMIT_CYCLES {
+                // The holder records its location just after acquiring the lock, so it is null if
+                // the lock's first holder hasn't yet done so. Locations passed by `#[track_caller]`
+                // are `'static`, so a non-null one is always valid.
+                let holder: &dyn Display =
+                    match unsafe { self.holder_location.load(Ordering::Acquire).as_ref() } {
+                        Some(location) => location,
+                        None => &"unknown location",
+                    };
+                panic!(
+                    "Deadlock: waited {spun} cycles at {} on CPU {}, for a lock held since {} on CPU {}",
+                    Location::caller(),
+                    arch::current_cpu(),
+                    holder,
+                    self.holder_cpu.load(Ordering::Relaxed),
+                );
+            }
+
+            spin_loop();
+        };
+
+        self.record_holder();
+        guard
+    }
+
+    /// Acquires the lock if it is available, without spinning, and returns `None` otherwise.
+    #[cfg_attr(feature = "lock-diagnostics", track_caller)]
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let guard = self.inner.try_lock()?;
+
+        #[cfg(feature = "lock-diagnostics")]
+        self.record_holder();
+        Some(guard)
+    }
+
+    /// Releases the lock, even though it is held.
+    ///
+    /// # Safety
+    ///
+    /// This must only be used when the holder of the lock will never release it, e.g., in the panic
+    /// handler, where the code holding the lock will never run again.
+    pub unsafe fn force_unlock(&self) {
+        unsafe { self.inner.force_unlock() }
+    }
+
+    /// Records the location of the caller of `lock()` or `try_lock()`, and the current CPU, as the
+    /// holder of the lock.
+    #[cfg(feature = "lock-diagnostics")]
+    #[track_caller]
+    fn record_holder(&self) {
+        self.holder_location.store(
+            ptr::from_ref(Location::caller()).cast_mut(),
+            Ordering::Release,
+        );
+        self.holder_cpu
+            .store(arch::current_cpu(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_lock_fails_while_locked() {
+        let mutex = Mutex::new(0);
+        let guard = mutex.lock();
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+        assert!(mutex.try_lock().is_some());
+    }
+
+    #[test]
+    fn force_unlock_releases_lock() {
+        let mutex = Mutex::new(0);
+        core::mem::forget(mutex.lock());
+        unsafe { mutex.force_unlock() };
+        *mutex.lock() += 1;
+        assert_eq!(*mutex.lock(), 1);
+    }
+}
@@ -0,0 +1,180 @@
+//! Pretty-prints the log records saved by passing `--log-records FILE` to the runner of phases
+//! from 29-structured-log onwards, optionally only those of a minimum level or a given module.
+//!
+//! Each line of the file is a JSON object written by the kernel's src/log_record.rs. Records are
+//! output one per line, with their time, level, module and message aligned in columns. The time is
+//! in seconds since the host booted if the record includes it, which it does when kvmclock is
+//! enabled, or otherwise in time stamp counter cycles since the first record.
+
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+/// The levels of log records, most severe first. These must match the names returned by
+/// `Level::name()` in the kernel's src/log_record.rs.
+const LEVELS: [&str; 4] = ["error", "warn", "info", "debug"];
+
+/// The options of the "log-records" subcommand.
+#[derive(Default)]
+pub struct Options {
+    /// Only records of this level or a more severe one are output.
+    pub level: Option<String>,
+    /// Only records of this module are output.
+    pub module: Option<String>,
+}
+
+/// A log record read from the file.
+struct Record {
+    tsc: u64,
+    ns: Option<u64>,
+    level: String,
+    module: String,
+    msg: String,
+}
+
+/// Outputs the log records in `file` that match `options`. Lines that aren't log records are
+/// reported and skipped. Exits with a failure status if the file can't be read or the level in
+/// `options` is unknown.
+pub fn run(file: &Path, options: &Options) {
+    let max_severity = match &options.level {
+        Some(level) => severity(level).unwrap_or_else(|| {
+            eprintln!("The level must be one of: {}", LEVELS.join(", "));
+            process::exit(1);
+        }),
+        None => LEVELS.len() - 1,
+    };
+
+    let contents = fs::read_to_string(file).unwrap_or_else(|e| {
+        eprintln!("Failed to read '{}': {e}", file.display());
+        process::exit(1);
+    });
+
+    let mut first_tsc = None;
+    for (index, line) in contents.lines().enumerate() {
+        let Some(record) = parse(line) else {
+            eprintln!("Line {} is not a log record", index + 1);
+            continue;
+        };
+        let first_tsc = *first_tsc.get_or_insert(record.tsc);
+
+        if !selected(&record, max_severity, options) {
+            continue;
+        }
+
+        let time = match record.ns {
+            Some(ns) => format!("{}.{:06}", ns / 1_000_000_000, ns % 1_000_000_000 / 1000),
+            None => record.tsc.wrapping_sub(first_tsc).to_string(),
+        };
+        println!(
+            "{time:>16} {:<5} {:<8} {}",
+            record.level, record.module, record.msg
+        );
+    }
+}
+
+/// Returns `true` if `record` should be output, i.e., its severity is at most `max_severity` and
+/// it is from the module in `options`, if any. Records of unknown levels are always output.
+fn selected(record: &Record, max_severity: usize, options: &Options) -> bool {
+    severity(&record.level).is_none_or(|severity| severity <= max_severity)
+        && options
+            .module
+            .as_ref()
+            .is_none_or(|module| *module == record.module)
+}
+
+/// Returns the position of `level` in `LEVELS`, where lower is more severe, or `None` if it is not
+/// a known level.
+fn severity(level: &str) -> Option<usize> {
+    LEVELS.iter().position(|&known| known == level)
+}
+
+/// Returns the log record in `line`, or `None` if it isn't one.
+fn parse(line: &str) -> Option<Record> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let string = |name| value.get(name)?.as_str().map(String::from);
+
+    Some(Record {
+        tsc: value.get("tsc")?.as_u64()?,
+        ns: value.get("ns").and_then(Value::as_u64),
+        level: string("level")?,
+        module: string("module")?,
+        msg: string("msg")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(level: &str, module: &str) -> Record {
+        Record {
+            tsc: 0,
+            ns: None,
+            level: String::from(level),
+            module: String::from(module),
+            msg: String::new(),
+        }
+    }
+
+    #[test]
+    fn parse_record() {
+        let record =
+            parse(r#"{"tsc":123,"ns":456,"level":"info","module":"init","msg":"fw_cfg done"}"#)
+                .unwrap();
+        assert_eq!(record.tsc, 123);
+        assert_eq!(record.ns, Some(456));
+        assert_eq!(record.level, "info");
+        assert_eq!(record.module, "init");
+        assert_eq!(record.msg, "fw_cfg done");
+    }
+
+    #[test]
+    fn parse_record_without_ns() {
+        let record =
+            parse(r#"{"tsc":123,"level":"warn","module":"main","msg":"No kvmclock"}"#).unwrap();
+        assert_eq!(record.ns, None);
+    }
+
+    #[test]
+    fn parse_record_with_escapes() {
+        let record =
+            parse(r#"{"tsc":1,"level":"debug","module":"boot","msg":"\"a\"\nb\\c"}"#).unwrap();
+        assert_eq!(record.msg, "\"a\"\nb\\c");
+    }
+
+    #[test]
+    fn parse_non_record() {
+        assert!(parse("Booting kernel").is_none());
+        assert!(parse(r#"{"tsc":1,"level":"info","module":"init"}"#).is_none());
+        assert!(parse(r#"{"tsc":"1","level":"info","module":"init","msg":""}"#).is_none());
+    }
+
+    #[test]
+    fn severity_order() {
+        assert_eq!(severity("error"), Some(0));
+        assert_eq!(severity("debug"), Some(3));
+        assert_eq!(severity("trace"), None);
+    }
+
+    #[test]
+    fn select_by_level() {
+        let options = Options::default();
+        let warn = severity("warn").unwrap();
+        assert!(selected(&record("error", "init"), warn, &options));
+        assert!(selected(&record("warn", "init"), warn, &options));
+        assert!(!selected(&record("info", "init"), warn, &options));
+        assert!(selected(&record("unknown", "init"), warn, &options));
+    }
+
+    #[test]
+    fn select_by_module() {
+        let options = Options {
+            level: None,
+            module: Some(String::from("fw_cfg")),
+        };
+        let debug = severity("debug").unwrap();
+        assert!(selected(&record("info", "fw_cfg"), debug, &options));
+        assert!(!selected(&record("info", "init"), debug, &options));
+    }
+}
@@ -0,0 +1,326 @@
+//! Builds, runs and tests the phases of simpleos from the top of the repository, so that each
+//! doesn't need to be built by hand in its own directory. It is run with `cargo xtask`:
+//!
+//! - `cargo xtask run PHASE [ARGS]...` builds PHASE's kernel and runs it in QEMU. Any ARGS are
+//!   passed to the phase's `add_uefi_boot`, e.g., `cargo xtask run 11 --boot-report report.txt`.
+//! - `cargo xtask image PHASE [--bios]` builds PHASE's kernel and creates a disk image that boots
+//!   via UEFI, or via BIOS if `--bios` is passed, without running it. Only phases from 18-xtask
+//!   onwards support this.
+//! - `cargo xtask test [--all-phases | PHASE...]` runs the tests of the shared library and of xtask
+//!   itself, then builds every phase or each PHASE given, and reports which failed. The kernels
+//!   never exit, so they are built but not run.
+//! - `cargo xtask qmp SOCKET COMMAND [ARGS]...` controls a running kernel through the QMP socket
+//!   SOCKET, which is created by passing `--qmp SOCKET` to the runner of phases from 22-qmp
+//!   onwards. COMMAND is one of:
+//!   - `type TEXT`, which types TEXT on the keyboard;
+//!   - `send-key KEY...`, which presses the keys with the given QMP codes at the same time, e.g.,
+//!     `send-key ctrl alt delete`;
+//!   - `screenshot FILE`, which saves the display to FILE in PPM format;
+//!   - `query NAME`, which outputs the result of QMP's `query-NAME` command, e.g., `query pci`;
+//!   - `reset`, which resets the virtual machine;
+//!   - `quit`, which exits QEMU;
+//!   - `savevm NAME`, `loadvm NAME` and `delvm NAME`, which save the state of the virtual machine
+//!     as the snapshot NAME, restore it, and delete it. These require the runner of phases from
+//!     23-snapshots onwards to be passed `--overlay FILE`, which snapshots are saved in.
+//! - `cargo xtask screenshot-test PHASE REFERENCE [--marker TEXT] [--tolerance PERCENT]
+//!   [--update]` boots PHASE's kernel and waits for it to output a line containing TEXT, which is
+//!   the last line of the boot time report by default. It then saves the display and compares it
+//!   with the PPM image REFERENCE, failing if more than PERCENT of the pixels differ, 0.1% by
+//!   default. `--update` saves the display to REFERENCE instead. Only phases from 22-qmp onwards
+//!   support this.
+//! - `cargo xtask log-records FILE [--level LEVEL] [--module MODULE]` pretty-prints the log records
+//!   in FILE, which is created by passing `--log-records FILE` to the runner of phases from
+//!   29-structured-log onwards. Only records of LEVEL or a more severe level are output if
+//!   `--level` is passed, where LEVEL is "error", "warn", "info" or "debug", and only those of
+//!   MODULE if `--module` is passed.
+//!
+//! PHASE is either the number at the start of a phase's directory name, e.g., "4" or "04", or the
+//! whole name.
+//!
+//! Each phase is a separate workspace with its own `rust-toolchain` file. Cargo is run in the
+//! phase's directory with `RUSTUP_TOOLCHAIN` removed from its environment, so that rustup selects
+//! the phase's toolchain rather than the one used to run this program.
+
+use qmp::Qmp;
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use std::process::{self, Command, ExitStatus};
+
+mod log_records;
+mod ppm;
+mod qmp;
+mod screenshot_test;
+
+// The options accepted by the "test", "image", "screenshot-test" and "log-records" subcommands.
+const ALL_PHASES_OPTION: &str = "--all-phases";
+const BIOS_OPTION: &str = "--bios";
+const MARKER_OPTION: &str = "--marker";
+const TOLERANCE_OPTION: &str = "--tolerance";
+const UPDATE_OPTION: &str = "--update";
+const LEVEL_OPTION: &str = "--level";
+const MODULE_OPTION: &str = "--module";
+
+// The runner's options that boot via BIOS, and that create the disk image without running it.
+// These must match `BIOS_OPTION` and `NO_RUN_OPTION` in add_uefi_boot/src/main.rs.
+const RUNNER_BIOS_OPTION: &str = "--bios";
+const RUNNER_NO_RUN_OPTION: &str = "--no-run";
+
+/// The package in each phase's workspace that creates a disk image and runs it in QEMU.
+const RUNNER_PACKAGE: &str = "add_uefi_boot";
+
+/// The package containing the code shared by the phases, which is tested on the host.
+const LIBRARY_PACKAGE: &str = "simpleos-kernel";
+
+/// This package, whose unit tests cover the parts of xtask that don't need QEMU.
+const XTASK_PACKAGE: &str = "xtask";
+
+fn main() {
+    let root = repository_root();
+    let mut args = env::args().skip(1);
+
+    match args.next().as_deref() {
+        Some("run") => {
+            let phase = find_phase(&root, &args.next().unwrap_or_else(|| usage()));
+            let runner_args: Vec<String> = args.collect();
+            exit_with(run_runner(&phase, &runner_args));
+        }
+        Some("image") => {
+            let phase = find_phase(&root, &args.next().unwrap_or_else(|| usage()));
+            let mut runner_args = vec![String::from(RUNNER_NO_RUN_OPTION)];
+            for arg in args {
+                match arg.as_str() {
+                    BIOS_OPTION => runner_args.push(String::from(RUNNER_BIOS_OPTION)),
+                    _ => usage(),
+                }
+            }
+            exit_with(run_runner(&phase, &runner_args));
+        }
+        Some("test") => {
+            let args: Vec<String> = args.collect();
+            let phases = match args.as_slice() {
+                [arg] if arg == ALL_PHASES_OPTION => all_phases(&root),
+                names => names.iter().map(|name| find_phase(&root, name)).collect(),
+            };
+            test(&root, &phases);
+        }
+        Some("qmp") => {
+            let socket = PathBuf::from(args.next().unwrap_or_else(|| usage()));
+            let command = args.next().unwrap_or_else(|| usage());
+            qmp_command(&socket, &command, &args.collect::<Vec<_>>());
+        }
+        Some("screenshot-test") => {
+            let phase = find_phase(&root, &args.next().unwrap_or_else(|| usage()));
+            let reference = PathBuf::from(args.next().unwrap_or_else(|| usage()));
+            let mut options = screenshot_test::Options::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    MARKER_OPTION => options.marker = args.next().unwrap_or_else(|| usage()),
+                    TOLERANCE_OPTION => {
+                        options.tolerance = args
+                            .next()
+                            .and_then(|tolerance| tolerance.parse().ok())
+                            .unwrap_or_else(|| usage());
+                    }
+                    UPDATE_OPTION => options.update = true,
+                    _ => usage(),
+                }
+            }
+            screenshot_test::run(&phase, &reference, &options);
+        }
+        Some("log-records") => {
+            let file = PathBuf::from(args.next().unwrap_or_else(|| usage()));
+            let mut options = log_records::Options::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    LEVEL_OPTION => options.level = Some(args.next().unwrap_or_else(|| usage())),
+                    MODULE_OPTION => options.module = Some(args.next().unwrap_or_else(|| usage())),
+                    _ => usage(),
+                }
+            }
+            log_records::run(&file, &options);
+        }
+        _ => usage(),
+    }
+}
+
+/// Prints a message describing the subcommands and exits.
+fn usage() -> ! {
+    eprintln!(
+        "Usage: cargo xtask run PHASE [ARGS]...\n       \
+         cargo xtask image PHASE [{BIOS_OPTION}]\n       \
+         cargo xtask test [{ALL_PHASES_OPTION} | PHASE...]\n       \
+         cargo xtask qmp SOCKET type TEXT | send-key KEY... | screenshot FILE | query NAME | reset \
+         | quit | savevm NAME | loadvm NAME | delvm NAME\n       \
+         cargo xtask screenshot-test PHASE REFERENCE [{MARKER_OPTION} TEXT] \
+         [{TOLERANCE_OPTION} PERCENT] [{UPDATE_OPTION}]\n       \
+         cargo xtask log-records FILE [{LEVEL_OPTION} LEVEL] [{MODULE_OPTION} MODULE]"
+    );
+    process::exit(1);
+}
+
+/// Returns the directory at the top of the repository, which contains this package's directory.
+fn repository_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("The xtask package is not in a subdirectory")
+        .to_path_buf()
+}
+
+/// Returns the directories of all phases, in order. A phase's directory name starts with its
+/// number followed by a hyphen, e.g., "04-print-macros".
+fn all_phases(root: &Path) -> Vec<PathBuf> {
+    let entries = fs::read_dir(root).unwrap_or_else(|e| {
+        eprintln!("Failed to read '{}': {e}", root.display());
+        process::exit(1);
+    });
+
+    let mut phases: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir() && phase_number(path).is_some())
+        .collect();
+    phases.sort();
+    phases
+}
+
+/// Returns the number at the start of the name of the phase in directory `phase`, or `None` if
+/// the directory's name doesn't start with a number followed by a hyphen.
+fn phase_number(phase: &Path) -> Option<u32> {
+    let name = phase.file_name()?.to_str()?;
+    let (number, _) = name.split_once('-')?;
+    number.parse().ok()
+}
+
+/// Returns the directory of the phase that `name` identifies. Exits if there is no such phase.
+fn find_phase(root: &Path, name: &str) -> PathBuf {
+    let number: Option<u32> = name.parse().ok();
+
+    all_phases(root)
+        .into_iter()
+        .find(|phase| {
+            phase.file_name().is_some_and(|dir_name| dir_name == name)
+                || number.is_some() && phase_number(phase) == number
+        })
+        .unwrap_or_else(|| {
+            eprintln!("There is no phase named or numbered '{name}'");
+            process::exit(1);
+        })
+}
+
+/// Builds the kernel of the phase in directory `phase`, and runs the phase's `add_uefi_boot` with
+/// `args`. Exits if the phase has no workspace, as is the case for the first phase, which must be
+/// built and run by hand.
+fn run_runner(phase: &Path, args: &[String]) -> ExitStatus {
+    if !phase.join("Cargo.toml").is_file() {
+        eprintln!(
+            "'{}' can't be run automatically. See its README.md for instructions.",
+            phase.display()
+        );
+        process::exit(1);
+    }
+
+    let mut cargo_args = vec!["run", "--package", RUNNER_PACKAGE, "--"];
+    cargo_args.extend(args.iter().map(String::as_str));
+    cargo(phase, &cargo_args)
+}
+
+/// Runs the tests of the shared library and of xtask, then builds each phase in `phases`. Outputs
+/// whether each passed, and exits with a failure status if any failed.
+fn test(root: &Path, phases: &[PathBuf]) {
+    let mut results: Vec<(String, bool)> = [LIBRARY_PACKAGE, XTASK_PACKAGE]
+        .iter()
+        .map(|&package| {
+            let passed = cargo(root, &["test", "--package", package]).success();
+            (String::from(package), passed)
+        })
+        .collect();
+
+    for phase in phases {
+        let name = phase.file_name().unwrap_or_default().to_string_lossy();
+        results.push((name.into_owned(), build_phase(phase)));
+    }
+
+    println!();
+    for (name, passed) in &results {
+        println!("{} {name}", if *passed { "PASS" } else { "FAIL" });
+    }
+
+    if results.iter().any(|(_, passed)| !passed) {
+        process::exit(1);
+    }
+}
+
+/// Builds every package of the phase in directory `phase`, returning `true` if all were built
+/// successfully. The first phase has no workspace, so each of its subdirectories containing a
+/// package is built separately.
+fn build_phase(phase: &Path) -> bool {
+    if phase.join("Cargo.toml").is_file() {
+        return cargo(phase, &["build", "--workspace"]).success();
+    }
+
+    let mut packages: Vec<PathBuf> = fs::read_dir(phase)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.join("Cargo.toml").is_file())
+                .collect()
+        })
+        .unwrap_or_default();
+    packages.sort();
+
+    !packages.is_empty()
+        && packages
+            .iter()
+            .all(|package| cargo(package, &["build"]).success())
+}
+
+/// Runs cargo with `args` in directory `dir`, and returns its exit status. Exits if cargo can't be
+/// run.
+fn cargo(dir: &Path, args: &[&str]) -> ExitStatus {
+    cargo_command(dir, args).status().unwrap_or_else(|e| {
+        eprintln!("Failed to run cargo: {e}");
+        process::exit(1);
+    })
+}
+
+/// Returns a command that runs cargo with `args` in directory `dir`.
+fn cargo_command(dir: &Path, args: &[&str]) -> Command {
+    println!("Running 'cargo {}' in '{}'", args.join(" "), dir.display());
+
+    let mut command = Command::new("cargo");
+    command
+        .args(args)
+        .current_dir(dir)
+        .env_remove("RUSTUP_TOOLCHAIN");
+    command
+}
+
+/// Connects to the QMP socket at `socket` and executes `command` with `args`. Exits with a failure
+/// status if the command fails.
+fn qmp_command(socket: &Path, command: &str, args: &[String]) {
+    let result = Qmp::connect(socket).and_then(|mut qmp| match (command, args) {
+        ("type", [text]) => qmp.type_text(text),
+        ("send-key", keys) if !keys.is_empty() => qmp.send_keys(keys),
+        ("screenshot", [file]) => path::absolute(file).and_then(|file| qmp.screendump(&file)),
+        ("query", [name]) => qmp
+            .execute(&format!("query-{name}"), json!({}))
+            .map(|result| println!("{result:#}")),
+        ("reset", []) => qmp.execute("system_reset", json!({})).map(drop),
+        ("quit", []) => qmp.execute("quit", json!({})).map(drop),
+        ("savevm" | "loadvm" | "delvm", [name]) => {
+            qmp.human_monitor_command(&format!("{command} {name}"))
+        }
+        _ => usage(),
+    });
+
+    if let Err(e) = result {
+        eprintln!("QMP error: {e}");
+        process::exit(1);
+    }
+}
+
+/// Exits with the same status as a process that exited with `status`.
+fn exit_with(status: ExitStatus) -> ! {
+    process::exit(status.code().unwrap_or(1));
+}
@@ -0,0 +1,172 @@
+//! Reads and compares images in the binary PPM format, which is the format QEMU's `screendump`
+//! command saves the display in. The format is described at
+//! <https://netpbm.sourceforge.net/doc/ppm.html>.
+
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// The largest difference between the values of a color channel of two pixels for which the
+/// pixels are treated as the same.
+const CHANNEL_TOLERANCE: u8 = 16;
+
+/// An image with 8-bit red, green and blue values for each pixel.
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Reads the PPM image in `file`. Returns an error if it isn't a binary PPM image with a
+    /// maximum color value of 255, which is the only kind QEMU saves.
+    pub fn load(file: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(file)?).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("'{}' is not a PPM image", file.display()),
+            )
+        })
+    }
+
+    /// Returns the PPM image in `data`, or `None` if it isn't a binary PPM image with a maximum
+    /// color value of 255.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        // The header consists of the "P6" magic number, the width, the height and the maximum
+        // color value, separated by whitespace and optionally comments. A single whitespace
+        // character separates it from the pixels.
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while fields.len() < 4 {
+            match data.get(pos)? {
+                b'#' => {
+                    while data.get(pos).is_some_and(|&b| b != b'\n') {
+                        pos += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => pos += 1,
+                _ => {
+                    let start = pos;
+                    while data.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                        pos += 1;
+                    }
+                    fields.push(&data[start..pos]);
+                }
+            }
+        }
+
+        let number =
+            |field: &[u8]| -> Option<usize> { std::str::from_utf8(field).ok()?.parse().ok() };
+        let (width, height) = match (
+            fields[0],
+            number(fields[1]),
+            number(fields[2]),
+            number(fields[3]),
+        ) {
+            (b"P6", Some(width), Some(height), Some(255)) => (width, height),
+            _ => return None,
+        };
+
+        // Dimensions so large that the size of the pixels overflows are invalid.
+        let pixels = data.get(pos + 1..)?;
+        if pixels.len() != width.checked_mul(height)?.checked_mul(3)? {
+            return None;
+        }
+
+        Some(Self {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        })
+    }
+
+    /// Returns the number of pixels in the image.
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Returns the number of pixels that differ between this image and `other`, or `None` if the
+    /// images have different sizes. Pixels are only treated as different if a color channel
+    /// differs by more than `CHANNEL_TOLERANCE`, so small changes in color are ignored.
+    pub fn differing_pixels(&self, other: &Image) -> Option<usize> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return None;
+        }
+
+        let count = self
+            .pixels
+            .chunks_exact(3)
+            .zip(other.pixels.chunks_exact(3))
+            .filter(|(a, b)| {
+                a.iter()
+                    .zip(b.iter())
+                    .any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE)
+            })
+            .count();
+        Some(count)
+    }
+
+    /// Returns the width and height of the image in pixels.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_bytes_header() {
+        let image =
+            Image::from_bytes(b"P6\n# QEMU screendump\n2 1 # size\n255\n\0\0\0\xff\xff\xff")
+                .unwrap();
+        assert_eq!(image.size(), (2, 1));
+        assert_eq!(image.pixels, [0, 0, 0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn from_bytes_rejects_invalid_headers() {
+        // Bad magic number.
+        assert!(Image::from_bytes(b"P3 1 1 255\n\0\0\0").is_none());
+        // Maximum color value other than 255.
+        assert!(Image::from_bytes(b"P6 1 1 65535\n\0\0\0\0\0\0").is_none());
+        // Truncated header.
+        assert!(Image::from_bytes(b"P6 1 1").is_none());
+        // Size of the pixels overflows.
+        assert!(Image::from_bytes(format!("P6 {} 2 255\n", usize::MAX).as_bytes()).is_none());
+    }
+
+    #[test]
+    fn from_bytes_rejects_wrong_pixel_length() {
+        assert!(Image::from_bytes(b"P6 2 1 255\n\0\0\0").is_none());
+        assert!(Image::from_bytes(b"P6 1 1 255\n\0\0\0\0").is_none());
+    }
+
+    #[test]
+    fn differing_pixels_tolerance() {
+        let image =
+            |value| Image::from_bytes(&[b"P6 1 1 255\n".as_slice(), &[value, 0, 0]].concat());
+        let black = image(0).unwrap();
+
+        assert_eq!(
+            black.differing_pixels(&image(CHANNEL_TOLERANCE - 1).unwrap()),
+            Some(0)
+        );
+        assert_eq!(
+            black.differing_pixels(&image(CHANNEL_TOLERANCE).unwrap()),
+            Some(0)
+        );
+        assert_eq!(
+            black.differing_pixels(&image(CHANNEL_TOLERANCE + 1).unwrap()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn differing_pixels_sizes() {
+        let wide = Image::from_bytes(b"P6 2 1 255\n\0\0\0\0\0\0").unwrap();
+        let tall = Image::from_bytes(b"P6 1 2 255\n\0\0\0\0\0\0").unwrap();
+        assert_eq!(wide.differing_pixels(&tall), None);
+    }
+}
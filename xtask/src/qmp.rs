@@ -0,0 +1,210 @@
+//! A client for the QEMU Machine Protocol (QMP), through which a running virtual machine can be
+//! controlled. Each message is a JSON object on a line of its own. The protocol is described at
+//! <https://www.qemu.org/docs/master/interop/qmp-spec.html>, and its commands at
+//! <https://www.qemu.org/docs/master/interop/qemu-qmp-ref.html>.
+
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+/// The QMP codes of the keys that type characters other than letters and digits on a US keyboard
+/// layout, and whether shift must be held to type each.
+const SYMBOL_KEYS: [(char, &str, bool); 25] = [
+    (' ', "spc", false),
+    ('\n', "ret", false),
+    ('\t', "tab", false),
+    ('-', "minus", false),
+    ('_', "minus", true),
+    ('=', "equal", false),
+    ('+', "equal", true),
+    ('[', "bracket_left", false),
+    ('{', "bracket_left", true),
+    (']', "bracket_right", false),
+    ('}', "bracket_right", true),
+    ('\\', "backslash", false),
+    ('|', "backslash", true),
+    (';', "semicolon", false),
+    (':', "semicolon", true),
+    ('\'', "apostrophe", false),
+    ('"', "apostrophe", true),
+    ('`', "grave_accent", false),
+    ('~', "grave_accent", true),
+    (',', "comma", false),
+    ('<', "comma", true),
+    ('.', "dot", false),
+    ('>', "dot", true),
+    ('/', "slash", false),
+    ('?', "slash", true),
+];
+
+/// The characters typed by holding shift and pressing each digit key, from 0 to 9.
+const SHIFTED_DIGITS: [char; 10] = [')', '!', '@', '#', '$', '%', '^', '&', '*', '('];
+
+/// A connection to QEMU's QMP socket.
+pub struct Qmp {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Qmp {
+    /// Connects to the QMP socket at `path`, and enters command mode, in which QEMU accepts
+    /// commands.
+    pub fn connect(path: &Path) -> io::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        let mut qmp = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+
+        // QEMU sends a greeting describing its version and capabilities, none of which are used.
+        qmp.read_message()?;
+        qmp.execute("qmp_capabilities", json!({}))?;
+        Ok(qmp)
+    }
+
+    /// Executes `command` with `arguments`, which must be a JSON object, and returns its result.
+    /// Returns an error if QEMU reports that the command failed.
+    pub fn execute(&mut self, command: &str, arguments: Value) -> io::Result<Value> {
+        let request = json!({ "execute": command, "arguments": arguments });
+        writeln!(self.writer, "{request}")?;
+
+        loop {
+            let mut message = self.read_message()?;
+            if let Some(result) = message.get_mut("return") {
+                return Ok(result.take());
+            }
+            if let Some(error) = message.get("error") {
+                let description = error["desc"].as_str().unwrap_or("no description");
+                return Err(io::Error::other(format!(
+                    "'{command}' failed: {description}"
+                )));
+            }
+
+            // Any other message is an event, such as the virtual machine being reset, which
+            // QEMU may send at any time.
+        }
+    }
+
+    /// Presses the keys with the given QMP codes at the same time, e.g., `["ctrl", "alt",
+    /// "delete"]`, then releases them.
+    pub fn send_keys<S: AsRef<str>>(&mut self, keys: &[S]) -> io::Result<()> {
+        let keys: Vec<Value> = keys
+            .iter()
+            .map(|key| json!({ "type": "qcode", "data": key.as_ref() }))
+            .collect();
+        self.execute("send-key", json!({ "keys": keys }))?;
+        Ok(())
+    }
+
+    /// Types `text` by pressing the keys that produce each character on a US keyboard layout.
+    /// Returns an error if `text` contains a character that can't be typed.
+    pub fn type_text(&mut self, text: &str) -> io::Result<()> {
+        for c in text.chars() {
+            let (key, shift) = char_key(c).ok_or_else(|| {
+                io::Error::new(ErrorKind::InvalidInput, format!("Can't type {c:?}"))
+            })?;
+
+            if shift {
+                self.send_keys(&["shift", &key])?;
+            } else {
+                self.send_keys(&[key])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the contents of the virtual machine's display to `file` in PPM format. `file` is
+    /// opened by QEMU, so a relative path is relative to QEMU's current directory.
+    pub fn screendump(&mut self, file: &Path) -> io::Result<()> {
+        self.execute("screendump", json!({ "filename": file }))?;
+        Ok(())
+    }
+
+    /// Executes `command_line` in QEMU's human monitor, for commands that have no QMP equivalent,
+    /// such as `savevm`. Returns an error if the command outputs anything, as these commands only
+    /// output error messages.
+    pub fn human_monitor_command(&mut self, command_line: &str) -> io::Result<()> {
+        let output = self.execute(
+            "human-monitor-command",
+            json!({ "command-line": command_line }),
+        )?;
+
+        match output.as_str().map(str::trim) {
+            Some("") => Ok(()),
+            Some(message) => Err(io::Error::other(format!(
+                "'{command_line}' failed: {message}"
+            ))),
+            None => Err(io::Error::other(format!(
+                "'{command_line}' returned {output}"
+            ))),
+        }
+    }
+
+    /// Reads the next message from QEMU.
+    fn read_message(&mut self) -> io::Result<Value> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "QEMU closed the QMP connection",
+            ));
+        }
+
+        serde_json::from_str(&line).map_err(io::Error::other)
+    }
+}
+
+/// Returns the QMP code of the key that types `c` on a US keyboard layout, and whether shift must
+/// be held while pressing it, or `None` if `c` can't be typed.
+fn char_key(c: char) -> Option<(String, bool)> {
+    if c.is_ascii_alphanumeric() {
+        return Some((c.to_ascii_lowercase().to_string(), c.is_ascii_uppercase()));
+    }
+
+    if let Some(digit) = SHIFTED_DIGITS.iter().position(|&shifted| shifted == c) {
+        return Some((digit.to_string(), true));
+    }
+
+    SYMBOL_KEYS
+        .iter()
+        .find(|&&(symbol, _, _)| symbol == c)
+        .map(|&(_, key, shift)| (String::from(key), shift))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: &str, shift: bool) -> Option<(String, bool)> {
+        Some((String::from(code), shift))
+    }
+
+    #[test]
+    fn char_key_letters_and_digits() {
+        assert_eq!(char_key('a'), key("a", false));
+        assert_eq!(char_key('Q'), key("q", true));
+        assert_eq!(char_key('7'), key("7", false));
+    }
+
+    #[test]
+    fn char_key_shifted_digits() {
+        assert_eq!(char_key('!'), key("1", true));
+        assert_eq!(char_key('('), key("9", true));
+        assert_eq!(char_key(')'), key("0", true));
+    }
+
+    #[test]
+    fn char_key_symbols() {
+        assert_eq!(char_key('-'), key("minus", false));
+        assert_eq!(char_key('_'), key("minus", true));
+        assert_eq!(char_key('}'), key("bracket_right", true));
+        assert_eq!(char_key('\n'), key("ret", false));
+    }
+
+    #[test]
+    fn char_key_untypeable() {
+        assert_eq!(char_key('é'), None);
+        assert_eq!(char_key('\u{7f}'), None);
+    }
+}
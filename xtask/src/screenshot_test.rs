@@ -0,0 +1,231 @@
+//! Checks that the display looks the same as in a previous run, by booting a phase's kernel,
+//! saving the display once the kernel has output a marker line, and comparing it with a reference
+//! image.
+
+use crate::ppm::Image;
+use crate::qmp::Qmp;
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// The runner's option that makes QEMU listen for QMP connections. This must match `QMP_OPTION`
+/// in add_uefi_boot/src/main.rs.
+const RUNNER_QMP_OPTION: &str = "--qmp";
+
+/// The line output last by the kernel at boot, which is the default marker. This must match the
+/// last line output by `report()` in the kernel's src/boot_timing.rs.
+const DEFAULT_MARKER: &str = "boot-time: total";
+
+/// The default percentage of pixels that may differ from the reference image.
+const DEFAULT_TOLERANCE: f64 = 0.1;
+
+/// The maximum time the kernel may take to output the marker once the runner has been started.
+const MARKER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The options of the "screenshot-test" subcommand.
+pub struct Options {
+    /// The text that the kernel outputs when the display is ready to be saved.
+    pub marker: String,
+    /// The percentage of pixels that may differ from the reference image.
+    pub tolerance: f64,
+    /// Saves the display as the new reference image rather than comparing it.
+    pub update: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            marker: String::from(DEFAULT_MARKER),
+            tolerance: DEFAULT_TOLERANCE,
+            update: false,
+        }
+    }
+}
+
+/// Builds and boots the kernel of the phase in directory `phase`, then waits for a line containing
+/// the marker and saves the display. The display is compared with the image in `reference`, or
+/// saved to it if `options.update` is `true`. Exits with a failure status if the images differ by
+/// more than the tolerance, in which case the display is saved next to `reference` so it can be
+/// examined.
+pub fn run(phase: &Path, reference: &Path, options: &Options) {
+    if !crate::build_phase(phase) {
+        fail(&format!("Failed to build '{}'", phase.display()));
+    }
+
+    // Temporary files are named after this process, so that tests can run at the same time.
+    let socket = env::temp_dir().join(format!("simpleos-{}.qmp", process::id()));
+    let screenshot = env::temp_dir().join(format!("simpleos-{}.ppm", process::id()));
+
+    let socket_arg = socket.to_string_lossy();
+    let mut runner = crate::cargo_command(
+        phase,
+        &[
+            "run",
+            "--package",
+            crate::RUNNER_PACKAGE,
+            "--",
+            RUNNER_QMP_OPTION,
+            &socket_arg,
+        ],
+    )
+    .stdout(Stdio::piped())
+    .spawn()
+    .unwrap_or_else(|e| fail(&format!("Failed to run cargo: {e}")));
+
+    let stdout = runner
+        .stdout
+        .take()
+        .expect("The runner's stdout was not captured");
+    let result = if wait_for_marker(stdout, &options.marker) {
+        Qmp::connect(&socket).and_then(|mut qmp| qmp.screendump(&screenshot))
+    } else {
+        Err(io::Error::other(format!(
+            "The kernel didn't output '{}' within {} seconds",
+            options.marker,
+            MARKER_TIMEOUT.as_secs()
+        )))
+    };
+
+    // QEMU is run by the runner, which is run by cargo, so it must be told to exit rather than
+    // being killed. This fails if QEMU has already exited, which doesn't matter.
+    let _ = Qmp::connect(&socket).and_then(|mut qmp| qmp.execute("quit", json!({})));
+    let _ = runner.wait();
+    let _ = fs::remove_file(&socket);
+
+    if let Err(e) = result {
+        fail(&format!("Failed to save the display: {e}"));
+    }
+
+    if options.update {
+        fs::copy(&screenshot, reference)
+            .unwrap_or_else(|e| fail(&format!("Failed to save the reference image: {e}")));
+        let _ = fs::remove_file(&screenshot);
+        println!("Saved the display to '{}'", reference.display());
+        return;
+    }
+
+    match compare(&screenshot, reference, options.tolerance) {
+        Ok(()) => {
+            let _ = fs::remove_file(&screenshot);
+            println!("PASS the display matches '{}'", reference.display());
+        }
+        Err(e) => {
+            let actual = actual_image_path(reference);
+            // The temporary directory may be on a different file system, so the file is copied.
+            let _ = fs::copy(&screenshot, &actual);
+            let _ = fs::remove_file(&screenshot);
+            fail(&format!(
+                "FAIL {e}. The display was saved to '{}'",
+                actual.display()
+            ));
+        }
+    }
+}
+
+/// Copies the lines of `output` to stdout, and returns `true` once a line containing `marker` has
+/// been read, or `false` if none is read within `MARKER_TIMEOUT`. The lines are read by another
+/// thread, which continues copying them to stdout so that QEMU is never blocked writing output.
+fn wait_for_marker(output: impl Read + Send + 'static, marker: &str) -> bool {
+    let (sender, receiver) = mpsc::channel();
+    let marker = String::from(marker);
+
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines() {
+            let Ok(line) = line else { break };
+            println!("{line}");
+
+            if line.contains(&marker) {
+                // The receiver is dropped once the marker is seen or the wait times out.
+                let _ = sender.send(());
+            }
+        }
+    });
+
+    receiver.recv_timeout(MARKER_TIMEOUT).is_ok()
+}
+
+/// Compares the image in `actual` with the one in `reference`. Returns an error describing how
+/// they differ if they have different sizes, or if more than `tolerance` percent of the pixels
+/// differ.
+fn compare(actual: &Path, reference: &Path, tolerance: f64) -> io::Result<()> {
+    compare_images(&Image::load(actual)?, &Image::load(reference)?, tolerance)
+}
+
+/// Compares the image `actual` with `reference`, in the same way as `compare()`. Empty images are
+/// treated as different, as the percentage of differing pixels is undefined.
+fn compare_images(actual: &Image, reference: &Image, tolerance: f64) -> io::Result<()> {
+    let Some(differing) = reference.differing_pixels(actual) else {
+        let (width, height) = actual.size();
+        let (reference_width, reference_height) = reference.size();
+        return Err(io::Error::other(format!(
+            "The display is {width}x{height} pixels, but the reference image is \
+             {reference_width}x{reference_height}"
+        )));
+    };
+
+    if reference.pixel_count() == 0 {
+        return Err(io::Error::other("The reference image is empty"));
+    }
+
+    let percentage = differing as f64 * 100.0 / reference.pixel_count() as f64;
+    if percentage > tolerance {
+        return Err(io::Error::other(format!(
+            "{percentage:.3}% of pixels differ from the reference image, more than the tolerance \
+             of {tolerance}%"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns the path the display is saved to if it doesn't match `reference`, e.g.,
+/// "boot-actual.ppm" for "boot.ppm".
+fn actual_image_path(reference: &Path) -> PathBuf {
+    let stem = reference.file_stem().unwrap_or_default().to_string_lossy();
+    reference.with_file_name(format!("{stem}-actual.ppm"))
+}
+
+/// Outputs `message` and exits with a failure status.
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a `width` by `height` image whose first `changed` pixels are white and the rest
+    /// black.
+    fn image(width: usize, height: usize, changed: usize) -> Image {
+        let mut data = format!("P6 {width} {height} 255\n").into_bytes();
+        for pixel in 0..width * height {
+            let value = if pixel < changed { 255 } else { 0 };
+            data.extend([value; 3]);
+        }
+        Image::from_bytes(&data).unwrap()
+    }
+
+    #[test]
+    fn compare_within_tolerance() {
+        // 1 of 1000 pixels is 0.1%.
+        assert!(compare_images(&image(100, 10, 1), &image(100, 10, 0), 0.1).is_ok());
+        assert!(compare_images(&image(100, 10, 2), &image(100, 10, 0), 0.1).is_err());
+    }
+
+    #[test]
+    fn compare_different_sizes() {
+        assert!(compare_images(&image(10, 10, 0), &image(10, 11, 0), 100.0).is_err());
+    }
+
+    #[test]
+    fn compare_empty_images() {
+        assert!(compare_images(&image(0, 0, 0), &image(0, 0, 0), 100.0).is_err());
+    }
+}